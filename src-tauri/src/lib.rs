@@ -1,36 +1,85 @@
 use std::{
-    collections::{HashMap, HashSet},
+    cmp::Reverse,
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet, VecDeque},
     fs::{self, create_dir_all, File},
-    io::{BufRead, BufReader},
+    io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    ops::Range,
     path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicBool, Ordering::Relaxed},
+        atomic::{AtomicBool, AtomicU64, Ordering::Relaxed},
         Arc, Mutex,
     },
+    thread,
+    time::SystemTime,
 };
 
-use chrono::{Duration, NaiveDateTime};
-use serde::{Deserialize, Serialize};
-use tauri::{AppHandle, Manager, State};
+use chrono::{Datelike, Duration, Local, Months, NaiveDate, NaiveDateTime, Timelike};
+use encoding_rs::{Encoding, WINDOWS_1252};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use flate2::read::MultiGzDecoder;
+use regex::RegexBuilder;
+use serde::{Deserialize, Deserializer, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+use unicode_segmentation::UnicodeSegmentation;
 use uuid::Uuid;
 
 /// App theme
-#[derive(Serialize, Deserialize, Copy, Clone)]
+#[derive(Serialize, Deserialize, Copy, Clone, Default)]
 enum Theme {
     /// Light theme
     LIGHT,
     /// Dark theme
     DARK,
     /// Unspecified theme
+    #[default]
     UNSPECIFIED,
 }
 
 /// The export version of a WhatsApp chat
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
 enum ExportVersion {
+    /// Lines start with `[date, time]`, and times include seconds
     OLD,
+    /// Lines start with `date, time -`, and times don't include seconds
     NEW,
 }
 
+/// Precision of the timestamps in a chat export
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+enum TimestampPrecision {
+    /// Timestamps include seconds
+    SECOND,
+    /// Timestamps only include hours and minutes
+    MINUTE,
+}
+
+/// Timestamp formats of old exports (see `ExportVersion`), in the order they're tried
+///
+/// The first is the usual format; the others handle day-first dates, 24-hour times, and times without seconds.
+const OLD_TIMESTAMP_FORMATS: [&str; 8] = [
+    "%m/%d/%y, %I:%M:%S %p",
+    "%d/%m/%y, %I:%M:%S %p",
+    "%m/%d/%y, %H:%M:%S",
+    "%d/%m/%y, %H:%M:%S",
+    "%m/%d/%y, %I:%M %p",
+    "%d/%m/%y, %I:%M %p",
+    "%m/%d/%y, %H:%M",
+    "%d/%m/%y, %H:%M",
+];
+
+/// Timestamp formats of new exports (see `ExportVersion`), in the order they're tried
+///
+/// The first is the usual format; the others handle day-first dates, 24-hour times, and ISO dates (used by some
+/// Android exports).
+const NEW_TIMESTAMP_FORMATS: [&str; 6] = [
+    "%m/%d/%y, %I:%M %p",
+    "%d/%m/%y, %I:%M %p",
+    "%m/%d/%y, %H:%M",
+    "%d/%m/%y, %H:%M",
+    "%Y-%m-%d, %H:%M",
+    "%Y-%m-%d, %I:%M %p",
+];
+
 /// Common photo extensions
 const PHOTO_TYPES: [&str; 15] = [
     "png", "apng", "jpg", "jpeg", "gif", "webp", "avif", "jfif", "pjpeg", "pjp", "svg", "bmp",
@@ -46,8 +95,228 @@ const AUDIO_TYPES: [&str; 5] = ["opus", "mp3", "aac", "ogg", "wav"];
 /// Extension to use for the cached chats
 const SAVE_NAME: &str = "chat_data.json";
 
+/// Name the cached chats are moved to if they can't be read
+const CORRUPT_SAVE_NAME: &str = "chat_data.corrupt.json";
+
+/// Directory (within the app data directory) that parsed chats are cached in when loading with `use_cache`
+const CHAT_CACHE_DIRECTORY: &str = "chat_cache";
+
+/// Current version of the parsed chat cache format; caches from other versions are ignored
+const CHAT_CACHE_VERSION: u32 = 2;
+
+/// Next chat revision to hand out (see `WhatsAppChat::revision`); it's shared by all chats so that a chat's revision
+/// keeps increasing even when the chat is parsed again
+static NEXT_REVISION: AtomicU64 = AtomicU64::new(0);
+
+/// Current version of the cached chats' schema
+///
+/// Version 0 is the unversioned format used before the version was saved.
+const SAVE_VERSION: u32 = 2;
+
+/// Fraction of a chat's media that must have been omitted from the export for it to be considered exported without
+/// media
+const MEDIA_STRIPPED_FRACTION: f64 = 0.9;
+
+/// Name of the manifest written by `export_media_bundle`
+const MEDIA_MANIFEST_NAME: &str = "manifest.json";
+
+/// Maximum number of recently viewed chats that are remembered
+const RECENT_CHATS_MAX: usize = 10;
+
+/// Notices appended to (or following) edited messages, in the languages WhatsApp exports in
+const EDITED_NOTICES: [&str; 8] = [
+    "<This message was edited>",
+    "<Se editó este mensaje.>",
+    "<Mensagem editada>",
+    "<Diese Nachricht wurde bearbeitet.>",
+    "<Ce message a été modifié>",
+    "<Questo messaggio è stato modificato>",
+    "<Dit bericht is bewerkt>",
+    "<Pesan ini telah diedit>",
+];
+
+/// Maximum number of text messages sampled when detecting a chat's language
+const LANGUAGE_SAMPLE_SIZE: usize = 2000;
+
+/// Notices replacing a message deleted by its sender, in the languages WhatsApp exports in
+const DELETED_NOTICES: [&str; 7] = [
+    "this message was deleted",
+    "se eliminó este mensaje",
+    "mensagem apagada",
+    "diese nachricht wurde gelöscht",
+    "ce message a été supprimé",
+    "questo messaggio è stato eliminato",
+    "dit bericht is verwijderd",
+];
+
+/// Notices replacing a message "you" deleted, in the languages WhatsApp exports in
+const YOU_DELETED_NOTICES: [&str; 7] = [
+    "you deleted this message",
+    "eliminaste este mensaje",
+    "você apagou esta mensagem",
+    "du hast diese nachricht gelöscht",
+    "vous avez supprimé ce message",
+    "hai eliminato questo messaggio",
+    "je hebt dit bericht verwijderd",
+];
+
+/// Start of the notice replacing a message deleted by a group admin; it's followed by the admin's name
+const ADMIN_DELETED_PREFIX: &str = "this message was deleted by admin ";
+
+/// Phrases identifying system messages about particular events, in the languages WhatsApp exports in
+///
+/// Phrases are matched in order, so a phrase contained in another (e.g. "pinned a message" in "unpinned a message")
+/// must come after it.
+const SYSTEM_EVENT_PHRASES: [(&str, SystemEvent); 12] = [
+    ("unpinned a message", SystemEvent::UNPINNED),
+    ("dejó de fijar un mensaje", SystemEvent::UNPINNED),
+    ("desafixou uma mensagem", SystemEvent::UNPINNED),
+    ("hat eine nachricht losgelöst", SystemEvent::UNPINNED),
+    ("a désépinglé un message", SystemEvent::UNPINNED),
+    ("ha sbloccato un messaggio", SystemEvent::UNPINNED),
+    ("pinned a message", SystemEvent::PINNED),
+    ("fijó un mensaje", SystemEvent::PINNED),
+    ("fixou uma mensagem", SystemEvent::PINNED),
+    ("hat eine nachricht angeheftet", SystemEvent::PINNED),
+    ("a épinglé un message", SystemEvent::PINNED),
+    ("ha fissato un messaggio", SystemEvent::PINNED),
+];
+
+/// Endings of system messages about people joining a group (see `membership_change`)
+const JOINED_SUFFIXES: [&str; 5] = [
+    " joined using this group's invite link",
+    " joined using this group’s invite link",
+    " joined using an invite link",
+    " joined from the community",
+    " joined",
+];
+
+/// Default words and emoji counted as positive by `get_sentiment_estimate`
+const POSITIVE_LEXICON: [&str; 40] = [
+    "love",
+    "loved",
+    "lovely",
+    "like",
+    "happy",
+    "glad",
+    "great",
+    "good",
+    "nice",
+    "awesome",
+    "amazing",
+    "wonderful",
+    "fantastic",
+    "excellent",
+    "perfect",
+    "beautiful",
+    "fun",
+    "funny",
+    "cool",
+    "thanks",
+    "thank",
+    "yay",
+    "congrats",
+    "congratulations",
+    "excited",
+    "enjoy",
+    "enjoyed",
+    "best",
+    "brilliant",
+    "sweet",
+    "haha",
+    "lol",
+    "😀",
+    "😂",
+    "😊",
+    "😍",
+    "🥰",
+    "❤",
+    "👍",
+    "🎉",
+];
+
+/// Default words and emoji counted as negative by `get_sentiment_estimate`
+const NEGATIVE_LEXICON: [&str; 36] = [
+    "hate", "hated", "sad", "angry", "mad", "upset", "bad", "awful", "terrible", "horrible",
+    "worst", "annoying", "annoyed", "sorry", "sick", "tired", "boring", "bored", "ugh", "damn",
+    "cry", "crying", "hurt", "wrong", "fail", "failed", "stress", "stressed", "worried", "😢",
+    "😭", "😞", "😠", "😡", "👎", "💔",
+];
+
+/// Fraction of messages sharing their timestamp with the previous message above which a warning is given
+const DUPLICATE_TIMESTAMP_WARNING_FRACTION: f64 = 0.5;
+
+/// How many levels of subfolders of a media directory are searched for media
+const MEDIA_DIRECTORY_MAX_DEPTH: usize = 4;
+
+/// Error returned when loading chats is cancelled
+const LOAD_CANCELLED: &str = "Cancelled";
+
+/// Number of messages `search_stream` scans between checking for cancellation and emitting results
+const SEARCH_BATCH_SIZE: usize = 2000;
+
+/// Number of entries in each "top" list of the Markdown stats report
+const REPORT_TOP_COUNT: usize = 10;
+
+/// Default gap (in minutes) between messages after which a new conversation is considered to have started
+const DEFAULT_CONVERSATION_GAP_MINUTES: i64 = 360;
+
+/// Default upper bounds (inclusive, in characters) of the buckets of `get_length_histogram`
+const DEFAULT_LENGTH_BUCKET_EDGES: [usize; 5] = [10, 50, 100, 250, 500];
+
+/// Default size (in bytes) above which a file is only parsed if it starts like a chat export
+const DEFAULT_MAX_CHAT_FILE_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Maximum number of bytes of the first line read to check whether a file larger than the maximum size is a chat
+const MAX_FIRST_LINE: u64 = 4096;
+
+/// Magic bytes at the start of a gzip-compressed file
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Format used for timestamps in exported files
+const EXPORT_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// (De)serializes timestamps as ISO-8601 strings (e.g. `2023-01-02T13:45:00`)
+mod iso8601 {
+    use chrono::NaiveDateTime;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    /// Format timestamps are serialized with
+    const FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+    pub fn serialize<S: Serializer>(
+        timestamp: &NaiveDateTime,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&timestamp.format(FORMAT))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<NaiveDateTime, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+
+    /// Serializes optional timestamps the same way as the parent module
+    pub mod option {
+        use chrono::NaiveDateTime;
+        use serde::Serializer;
+
+        pub fn serialize<S: Serializer>(
+            timestamp: &Option<NaiveDateTime>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match timestamp {
+                Some(t) => super::serialize(t, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+    }
+}
+
 /// The type of the media
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 enum MediaType {
     /// A photo
     PHOTO,
@@ -59,15 +328,82 @@ enum MediaType {
     OTHER,
 }
 
+/// An event described by a system message
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+enum SystemEvent {
+    /// A message was pinned (which message isn't included in exports)
+    PINNED,
+    /// A message was unpinned
+    UNPINNED,
+    /// People were added to the group by someone else
+    ADDED,
+    /// People were removed from the group by someone else
+    REMOVED,
+    /// Someone joined the group themselves (such as with an invite link) or created it
+    JOINED,
+    /// Someone left the group
+    LEFT,
+}
+
+/// Kind of message content to filter by
+#[derive(Deserialize, Copy, Clone, PartialEq, Eq)]
+enum ContentKind {
+    /// Text messages
+    TEXT,
+    /// Media messages of any type
+    MEDIA,
+    /// Photo messages
+    PHOTO,
+    /// Video messages
+    VIDEO,
+    /// Audio messages
+    AUDIO,
+    /// Other file messages
+    OTHER,
+    /// System messages
+    SYSTEM,
+    /// Deleted messages
+    DELETED,
+}
+
+impl ContentKind {
+    /// Checks whether `content` is of this kind
+    fn matches(&self, content: &MessageContent) -> bool {
+        match (self, content) {
+            (ContentKind::TEXT, MessageContent::Text(_)) => true,
+            (ContentKind::SYSTEM, MessageContent::System(_)) => true,
+            (ContentKind::DELETED, MessageContent::Deleted(_)) => true,
+            (ContentKind::MEDIA, MessageContent::Media(_)) => true,
+            (ContentKind::PHOTO, MessageContent::Media(m)) => m.media_type == MediaType::PHOTO,
+            (ContentKind::VIDEO, MessageContent::Media(m)) => m.media_type == MediaType::VIDEO,
+            (ContentKind::AUDIO, MessageContent::Media(m)) => m.media_type == MediaType::AUDIO,
+            (ContentKind::OTHER, MessageContent::Media(m)) => m.media_type == MediaType::OTHER,
+            _ => false,
+        }
+    }
+
+    /// Checks whether `content` is any of `kinds`; if no kinds are given, all content is allowed
+    fn any_matches(kinds: &Option<Vec<ContentKind>>, content: &MessageContent) -> bool {
+        match kinds {
+            Some(kinds) => kinds.iter().any(|k| k.matches(content)),
+            None => true,
+        }
+    }
+}
+
 /// Represents a media message
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 struct Media {
     /// Media type
     media_type: MediaType,
     /// Media path, if available
     path: Option<String>,
+    /// Name of the attached file, if known
+    file_name: Option<String>,
     /// Caption, if any
     caption: Option<String>,
+    /// Whether the media is a recorded voice note (rather than e.g. a music file)
+    voice_note: bool,
 }
 
 impl PartialEq for Media {
@@ -91,8 +427,17 @@ impl PartialEq for Media {
 }
 impl Eq for Media {}
 
+/// Who deleted a message
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Deletion {
+    /// Whether the message was deleted by "you"
+    deleted_by_you: bool,
+    /// Name of the group admin who deleted the message, if it was deleted by one
+    deleted_by_admin: Option<String>,
+}
+
 /// The content of a WhatsApp message
-#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 enum MessageContent {
     /// A standard text message
     Text(String),
@@ -100,12 +445,15 @@ enum MessageContent {
     Media(Media),
     /// A system message (such as changing the group name)
     System(String),
+    /// A message that was deleted after being sent
+    Deleted(Deletion),
 }
 
 /// A single WhatsApp message
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 struct Message {
     /// When the message was sent
+    #[serde(with = "iso8601")]
     timestamp: NaiveDateTime,
     /// Who send the message, if anyone
     sender: Option<String>,
@@ -115,6 +463,26 @@ struct Message {
     starred: AtomicBool,
     /// Index of the message in its chat, if known
     idx: usize,
+    /// Whether the message was edited after being sent
+    edited: bool,
+    /// Whether the message matches one of the chat's automated message patterns (such as a business greeting)
+    #[serde(default)]
+    automated: bool,
+    /// Line numbers (counting from 1) of the lines of the chat file the message was parsed from
+    #[serde(skip)]
+    source_lines: Range<usize>,
+    /// The event the message describes, if it's a recognized system message
+    #[serde(default)]
+    system_event: Option<SystemEvent>,
+}
+
+impl Message {
+    /// Gets a copy of the message with its sender redacted if it's a phone number (see `redact_phone_number`)
+    fn with_redacted_sender(&self) -> Message {
+        let mut message = self.clone();
+        message.sender = message.sender.map(|s| redact_phone_number(&s));
+        message
+    }
 }
 
 impl Clone for Message {
@@ -125,6 +493,10 @@ impl Clone for Message {
             content: self.content.clone(),
             starred: AtomicBool::new(self.starred.load(Relaxed)),
             idx: self.idx,
+            edited: self.edited,
+            automated: self.automated,
+            source_lines: self.source_lines.clone(),
+            system_event: self.system_event,
         };
     }
 }
@@ -158,17 +530,110 @@ struct WhatsAppChat {
     directories: Vec<String>,
     /// Chat name
     name: String,
-    /// Which message sender is considered to be "you"
-    you: Arc<Mutex<Option<String>>>,
+    /// Which message senders are considered to be "you" (a person may appear under several names)
+    you: Arc<Mutex<Vec<String>>>,
+    /// Offset (in minutes) applied to the exported timestamps, if any
+    tz_offset_minutes: Option<i32>,
+    /// Stable identifier of each message (in the same order as `messages`), for deep linking
+    anchors: Vec<String>,
+    /// Display color chosen for each sender, if any
+    sender_colors: Arc<Mutex<HashMap<String, String>>>,
+    /// Whether senders whose names differ only in case were treated as the same person
+    merge_sender_case: bool,
+    /// Patterns identifying automated messages (such as business greetings and away messages)
+    automated_patterns: Vec<String>,
+    /// Export version the chat was parsed as, if it was given instead of detected
+    format_hint: Option<ExportVersion>,
+    /// Label of the encoding the chat file was read with, if it was given instead of detected
+    encoding: Option<String>,
+    /// Index of the last message that has been read, if any
+    last_read_idx: Arc<Mutex<Option<usize>>>,
+    /// Gap (in minutes) between messages after which a new conversation starts, if it's been set for this chat
+    gap_threshold_minutes: Arc<Mutex<Option<i64>>>,
+    /// Precision of the exported timestamps
+    timestamp_precision: TimestampPrecision,
+    /// Format the message timestamps were parsed with (see `OLD_TIMESTAMP_FORMATS` and `NEW_TIMESTAMP_FORMATS`)
+    timestamp_format: String,
+    /// Message counts by sender, if they've been computed (see `count_by_sender`)
+    #[serde(skip)]
+    counts_cache: SenderCountsCache,
+    /// Revision of the messages, which increases whenever they change; it's taken from `NEXT_REVISION`, so it also
+    /// increases when the chat is replaced with a modified copy or parsed again
+    #[serde(skip)]
+    revision: Arc<AtomicU64>,
+    /// Revision of which messages are starred, which is kept separately from `revision` since stars don't affect
+    /// anything computed from the messages (such as `counts_cache`)
+    #[serde(skip)]
+    stars_revision: Arc<AtomicU64>,
+}
+
+/// A chat, with or without its messages
+#[derive(Serialize)]
+#[serde(untagged)]
+enum ChatPayload {
+    /// The whole chat, including its messages
+    Full(Arc<WhatsAppChat>),
+    /// Just information about the chat
+    Metadata(ChatMetadata),
+}
+
+/// Information about a chat, without its messages
+#[derive(Serialize)]
+struct ChatMetadata {
+    /// Unique identifier of the chat
+    id: Uuid,
+    /// Chat name
+    name: String,
+    /// Chat file
+    file: String,
+    /// Resource directories, if available
+    directories: Vec<String>,
+    /// Which message senders are considered to be "you"
+    you: Vec<String>,
+    /// Number of messages in the chat
+    number_of_messages: usize,
+    /// Number of messages sent by each person in the chat broken down by type
+    counts: HashMap<String, MessageTypeCount>,
+    /// Offset (in minutes) applied to the exported timestamps, if any
+    tz_offset_minutes: Option<i32>,
+    /// Precision of the exported timestamps
+    timestamp_precision: TimestampPrecision,
+    /// Format the message timestamps were parsed with
+    timestamp_format: String,
+}
+
+/// Deserializes the "you" senders from either a list or a single (possibly null) sender, as saved by older versions
+fn deserialize_you<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(Option<String>),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(you) => you.into_iter().collect(),
+        OneOrMany::Many(you) => you,
+    })
 }
 
 /// Basic information about a chat
 #[derive(Serialize, Deserialize)]
 struct SavedChats {
+    /// Schema version of the saved data
+    #[serde(default)]
+    version: u32,
     /// Program theme
+    #[serde(default)]
     theme: Theme,
     /// Saved chats
+    #[serde(default)]
     chats: Vec<BasicChatDataWithStars>,
+    /// IDs of the most recently viewed chats, most recent first
+    #[serde(default)]
+    recent: Vec<Uuid>,
+    /// Warnings when the saved data was read; these are never saved
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
 }
 
 /// Basic information chat a chat, including starred messages
@@ -183,9 +648,35 @@ struct BasicChatDataWithStars {
     /// Chat name
     name: String,
     /// Starred message indices
+    #[serde(default)]
     starred: Vec<usize>,
-    /// Sender to mark as "you"
-    you: Option<String>,
+    /// Senders to mark as "you"
+    #[serde(default, deserialize_with = "deserialize_you")]
+    you: Vec<String>,
+    /// Offset (in minutes) applied to the exported timestamps, if any
+    #[serde(default)]
+    tz_offset_minutes: Option<i32>,
+    /// Display color chosen for each sender; senders without one are colored automatically
+    #[serde(default)]
+    sender_colors: HashMap<String, String>,
+    /// Whether senders whose names differ only in case are the same person
+    #[serde(default)]
+    merge_sender_case: bool,
+    /// Patterns identifying automated messages
+    #[serde(default)]
+    automated_patterns: Vec<String>,
+    /// Export version to parse the chat as, if it shouldn't be detected
+    #[serde(default)]
+    format_hint: Option<ExportVersion>,
+    /// Label of the encoding to read the chat file with, if it shouldn't be detected
+    #[serde(default)]
+    encoding: Option<String>,
+    /// Index of the last message that has been read, if any
+    #[serde(default)]
+    last_read_idx: Option<usize>,
+    /// Gap (in minutes) between messages after which a new conversation starts, if it's been set for this chat
+    #[serde(default)]
+    gap_threshold_minutes: Option<i64>,
 }
 
 /// Summary of a WhatsApp chat
@@ -193,31 +684,174 @@ struct BasicChatDataWithStars {
 struct ChatSummary {
     /// Warnings when the chat was loaded
     warnings: Vec<String>,
+    /// Unique chat ID
+    id: Uuid,
     /// Chat name
     name: String,
     /// When the first message was sent; this is only `None` if no messages were sent
+    #[serde(with = "iso8601::option")]
     first_sent: Option<NaiveDateTime>,
     /// When the last message was sent; this is only `None` if no messages were sent
+    #[serde(with = "iso8601::option")]
     last_sent: Option<NaiveDateTime>,
     /// The last message that was sent; this is only `None` if no messages were sent
     last_message: Option<Message>,
     /// The total number of messages
     number_of_messages: usize,
+    /// Number of media messages whose file was found
+    media_resolved: u64,
+    /// Number of media messages with a file name whose file wasn't found
+    media_unresolved: u64,
+    /// Number of media messages that were omitted from the export
+    media_omitted: u64,
     /// Starred messages
     starred: Vec<Message>,
-    /// Which sender is considered "you"
-    you: Option<String>,
+    /// Which senders are considered "you"
+    you: Vec<String>,
+    /// Offset (in minutes) that was applied to the exported timestamps, if any
+    tz_offset_minutes: Option<i32>,
+    /// Index of the last message that has been read, if any
+    last_read_idx: Option<usize>,
+}
+
+impl ChatSummary {
+    /// Gets the summary with the senders that are phone numbers redacted (see `redact_phone_number`)
+    fn with_redacted_numbers(self) -> ChatSummary {
+        ChatSummary {
+            you: self.you.iter().map(|y| redact_phone_number(y)).collect(),
+            last_message: self.last_message.map(|m| m.with_redacted_sender()),
+            starred: self
+                .starred
+                .iter()
+                .map(Message::with_redacted_sender)
+                .collect(),
+            ..self
+        }
+    }
+}
+
+/// The "you" aliases of a chat
+#[derive(Serialize)]
+struct ChatYou {
+    /// Chat name
+    name: String,
+    /// Which senders are considered "you"
+    you: Vec<String>,
+}
+
+/// How to order the list of chats
+#[derive(Deserialize, Clone, Copy)]
+#[allow(non_camel_case_types)]
+enum ChatOrder {
+    /// Most recently active first
+    LAST_SENT,
+    /// Most messages first
+    MESSAGE_COUNT,
+    /// Alphabetically by name
+    NAME,
+}
+
+/// Length of time to group messages by
+#[derive(Deserialize, Clone, Copy)]
+enum Granularity {
+    /// Calendar days
+    DAY,
+    /// Weeks starting on Monday
+    WEEK,
+    /// Calendar months
+    MONTH,
+    /// Calendar years
+    YEAR,
+}
+
+impl Granularity {
+    /// Gets the first day of the period containing `date`
+    fn period_start(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Granularity::DAY => date,
+            Granularity::WEEK => {
+                date - Duration::days(date.weekday().num_days_from_monday().into())
+            }
+            Granularity::MONTH => date.with_day(1).unwrap_or(date),
+            Granularity::YEAR => date.with_ordinal(1).unwrap_or(date),
+        }
+    }
+
+    /// Gets the first day of the period after the one starting on `start`
+    fn next_period(&self, start: NaiveDate) -> NaiveDate {
+        match self {
+            Granularity::DAY => start + Duration::days(1),
+            Granularity::WEEK => start + Duration::days(7),
+            Granularity::MONTH => start + Months::new(1),
+            Granularity::YEAR => start + Months::new(12),
+        }
+    }
+
+    /// Gets the start of every period from the one containing `first` to the one containing `last` (inclusive)
+    fn periods(&self, first: NaiveDate, last: NaiveDate) -> Vec<NaiveDate> {
+        let mut periods = Vec::new();
+        let mut start = self.period_start(first);
+        while start <= last {
+            periods.push(start);
+            start = self.next_period(start);
+        }
+        periods
+    }
+}
+
+/// Lightweight summary of a chat, for listing chats
+#[derive(Serialize)]
+struct ChatListEntry {
+    /// Unique chat ID
+    id: Uuid,
+    /// Chat name
+    name: String,
+    /// When the last message was sent; this is only `None` if no messages were sent
+    #[serde(with = "iso8601::option")]
+    last_sent: Option<NaiveDateTime>,
+    /// The total number of messages
+    number_of_messages: usize,
 }
 
 /// Count of each message type
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Default, Debug, Serialize)]
 struct MessageTypeCount {
     /// Number of text messages
     text: u64,
     /// Number of media messages of each type
     media: MediaTypeCount,
+    /// Number of media messages with a caption
+    captioned_media: u64,
     /// Number of system messages
     system: u64,
+    /// Number of deleted messages
+    deleted: u64,
+    /// Number of automated messages (see `is_automated`)
+    automated: u64,
+}
+
+/// Memoized per-sender message counts of a chat
+///
+/// The counts are always computed from all of the messages, and then reused until they're stale. A copy of a chat
+/// starts with an empty cache, since copies are made to be modified.
+#[derive(Default)]
+struct SenderCountsCache(Mutex<Option<CachedSenderCounts>>);
+
+impl Clone for SenderCountsCache {
+    fn clone(&self) -> SenderCountsCache {
+        SenderCountsCache::default()
+    }
+}
+
+/// Per-sender message counts, along with what they were counted with; if either has changed since, all of the
+/// messages are counted again
+struct CachedSenderCounts {
+    /// The "you" aliases the messages were counted with
+    you: Vec<String>,
+    /// Revision of the chat when the messages were counted
+    revision: u64,
+    /// Count of each message type by sender
+    counts: HashMap<String, MessageTypeCount>,
 }
 
 /// Count of each media type
@@ -227,792 +861,6237 @@ struct MediaTypeCount {
     photo: u64,
     /// Number of videos
     video: u64,
-    /// Number of audio files
+    /// Number of audio files, excluding voice notes
     audio: u64,
+    /// Number of voice notes
+    voice_note: u64,
     /// Number of other files
     other: u64,
 }
 
-/// A chat from the frontend to load
-#[derive(Deserialize)]
-#[allow(non_snake_case)]
-struct ChatToLoad {
-    /// Unique chat ID
-    id: Uuid,
-    /// Chat file path
-    file: String,
-    /// Chat resource directory path
-    directory: Option<String>,
-    /// Chat name
-    name: String,
-    /// Indices of the starred messages
-    starred: Vec<usize>,
-    /// Which sender is "you"
-    you: Option<String>,
+/// Someone joining or leaving a group, for `get_membership_timeline`
+#[derive(Serialize)]
+struct MembershipChange {
+    /// Index of the system message describing the change
+    idx: usize,
+    /// When the change happened
+    #[serde(with = "iso8601")]
+    timestamp: NaiveDateTime,
+    /// Who joined or left
+    member: String,
+    /// How they joined or left
+    event: SystemEvent,
 }
 
-struct ParsedWhatsAppChat {
-    /// Warning messages during loading
-    warnings: Vec<String>,
-    /// Parsed chat
-    chat: WhatsAppChat,
+/// A stretch of time someone was in a group, for `get_membership_timeline`
+#[derive(Serialize)]
+struct MembershipPeriod {
+    /// Who was in the group
+    member: String,
+    /// When they joined; this is `None` if they were already in the group when the export starts
+    #[serde(with = "iso8601::option")]
+    joined: Option<NaiveDateTime>,
+    /// When they left; this is `None` if they're still in the group
+    #[serde(with = "iso8601::option")]
+    left: Option<NaiveDateTime>,
 }
 
-/// Maintains the app state
-struct AppState {
-    /// Mapping of chat names to chat objects
-    chats: Mutex<Vec<Arc<WhatsAppChat>>>,
-    /// App theme
-    theme: Mutex<Theme>,
+/// Who was in a group when
+#[derive(Serialize)]
+struct MembershipTimeline {
+    /// Every change in membership, in order
+    changes: Vec<MembershipChange>,
+    /// Every stretch of time each person was in the group, in order of when they joined (or left, if they were already
+    /// in the group when the export starts)
+    periods: Vec<MembershipPeriod>,
 }
 
-impl WhatsAppChat {
-    /// Gets the number of messages sent by each person in the chat broken down by type
-    fn count_by_sender(&self) -> HashMap<String, MessageTypeCount> {
-        let mut to_return: HashMap<String, MessageTypeCount> = HashMap::new();
-        self.messages.iter().for_each(|m| match &m.sender {
-            Some(s) => match to_return.get_mut(s) {
-                Some(mtc) => match &m.content {
-                    MessageContent::Text(_) => mtc.text += 1,
-                    MessageContent::System(_) => mtc.system += 1,
-                    MessageContent::Media(mm) => match mm.media_type {
-                        MediaType::PHOTO => mtc.media.photo += 1,
-                        MediaType::VIDEO => mtc.media.video += 1,
-                        MediaType::AUDIO => mtc.media.audio += 1,
-                        MediaType::OTHER => mtc.media.other += 1,
-                    },
-                },
-                None => {
-                    match &m.content {
-                        MessageContent::Text(_) => {
-                            to_return.insert(
-                                s.clone(),
-                                MessageTypeCount {
-                                    text: 1,
-                                    media: MediaTypeCount::default(),
-                                    system: 0,
-                                },
-                            );
-                        }
-                        MessageContent::System(_) => {
-                            to_return.insert(
-                                s.clone(),
-                                MessageTypeCount {
-                                    text: 0,
-                                    media: MediaTypeCount::default(),
-                                    system: 1,
-                                },
-                            );
-                        }
-                        MessageContent::Media(mm) => {
-                            let media_type_count = match mm.media_type {
-                                MediaType::PHOTO => MediaTypeCount {
-                                    photo: 1,
-                                    video: 0,
-                                    audio: 0,
-                                    other: 0,
-                                },
-                                MediaType::VIDEO => MediaTypeCount {
-                                    photo: 0,
-                                    video: 1,
-                                    audio: 0,
-                                    other: 0,
-                                },
-                                MediaType::AUDIO => MediaTypeCount {
-                                    photo: 0,
-                                    video: 0,
-                                    audio: 1,
-                                    other: 0,
-                                },
-                                MediaType::OTHER => MediaTypeCount {
-                                    photo: 0,
-                                    video: 0,
-                                    audio: 0,
-                                    other: 1,
-                                },
-                            };
-                            to_return.insert(
-                                s.clone(),
-                                MessageTypeCount {
-                                    text: 0,
-                                    media: media_type_count,
-                                    system: 0,
-                                },
-                            );
-                        }
-                    };
-                }
-            },
-            _ => {}
-        });
-        return to_return;
-    }
+/// The first message of a calendar day
+#[derive(Serialize)]
+struct DayBoundary {
+    /// Index of the first message sent on `date`
+    idx: usize,
+    /// The (local) date of the message
+    date: NaiveDate,
 }
 
-/// Saves basic information about the specified chats
-/// # Parameters
-/// * `directory` - Directory to which the chat information should be saved
-/// * `chats` - Chats to save
-fn save_basic_chat_data(
-    directory: &PathBuf,
-    chats: &Vec<Arc<WhatsAppChat>>,
-    theme: Theme,
-) -> Result<(), String> {
-    create_dir_all(directory).map_err(|err| err.to_string())?;
-    let mut basic_data = Vec::with_capacity(chats.len());
-    for c in chats {
-        let you = c
-            .you
-            .lock()
-            .or(Err("Failed to get lock on state".to_owned()))?
-            .clone();
-        basic_data.push(BasicChatDataWithStars {
-            id: c.id,
-            file: c.file.clone(),
-            directory: c.directories.first().cloned(),
-            name: c.name.clone(),
-            starred: c
-                .messages
-                .iter()
-                .enumerate()
-                .filter_map(|(idx, m)| {
-                    if m.starred.load(Relaxed) {
-                        Some(idx)
-                    } else {
-                        None
-                    }
-                })
-                .collect(),
-            you,
-        });
-    }
-    let f = fs::File::create(directory.join(SAVE_NAME)).map_err(|e| e.to_string())?;
-    serde_json::to_writer(
-        f,
-        &SavedChats {
-            theme,
-            chats: basic_data,
-        },
-    )
-    .map_err(|e| e.to_string())
+/// Summary of a media message, for the gallery
+#[derive(Serialize)]
+struct MediaSummary {
+    /// Media type
+    media_type: MediaType,
+    /// Whether the media is a voice note
+    voice_note: bool,
+    /// Media path, if available
+    path: Option<String>,
+    /// Caption, if any
+    caption: Option<String>,
+    /// When the media was sent
+    #[serde(with = "iso8601")]
+    timestamp: NaiveDateTime,
+    /// Index of the message in its chat
+    idx: usize,
 }
 
-/// Searches the messages in `chat` for the given string
-/// # Args
-/// * `chat` - Name of the chat to search
-/// * `search` - String to search
-#[tauri::command]
-fn search(chat: String, search: String, state: State<'_, AppState>) -> Result<Vec<usize>, String> {
-    let locked_chats = state
+/// Count of each media type sent by one sender, for the gallery
+#[derive(Default, Serialize)]
+struct GalleryMediaCount {
+    /// Number of media messages of each type whose file was found
+    resolved: MediaTypeCount,
+    /// Number of media messages that were omitted from the export or whose file wasn't found
+    unresolved: u64,
+}
+
+/// Media messages grouped by sender, for the gallery
+#[derive(Serialize)]
+struct GalleryMedia {
+    /// Each sender's media messages
+    media: HashMap<String, Vec<MediaSummary>>,
+    /// Breakdown of each sender's media messages; the counts for each sender add up to the length of their list in `media`
+    counts: HashMap<String, GalleryMediaCount>,
+}
+
+/// A media file copied by `export_media_bundle`, as listed in the bundle's manifest
+#[derive(Serialize)]
+struct BundledMedia {
+    /// Name of the copied file in the bundle
+    file: String,
+    /// Name of the original file
+    original: String,
+    /// When the media was sent
+    #[serde(with = "iso8601")]
+    timestamp: NaiveDateTime,
+    /// Who sent the media
+    sender: Option<String>,
+    /// Caption, if any
+    caption: Option<String>,
+    /// Index of the message in its chat
+    idx: usize,
+}
+
+/// Result of `export_media_bundle`
+#[derive(Serialize)]
+struct MediaBundleResult {
+    /// Number of media files copied
+    copied: usize,
+    /// Indices of the media messages that weren't copied because their file wasn't found
+    skipped: Vec<usize>,
+}
+
+/// Whether a chat appears to have been exported without its media
+#[derive(Serialize)]
+struct MediaStrippedEstimate {
+    /// Number of media messages whose file was found
+    resolved: u64,
+    /// Number of media messages whose file name is known but wasn't found
+    unresolved: u64,
+    /// Number of media messages that were omitted from the export
+    omitted: u64,
+    /// Fraction of media messages that were omitted; this is `None` if there are no media messages
+    omitted_fraction: Option<f64>,
+    /// Whether at least `MEDIA_STRIPPED_FRACTION` of the media was omitted, so the export probably has no media files
+    stripped: bool,
+}
+
+/// Metrics about a single sender, for comparing senders
+#[derive(Serialize)]
+struct SenderMetrics {
+    /// The sender
+    sender: String,
+    /// Number of messages of each type
+    counts: MessageTypeCount,
+    /// Average length (in characters) of text messages, if any were sent
+    average_length: Option<f64>,
+    /// Number of emoji used in text messages and captions
+    emoji: u64,
+    /// Number of text messages and captions asking a question
+    questions: u64,
+    /// Number of days on which the sender sent the first message
+    first_of_day: u64,
+}
+
+/// Side-by-side metrics of two senders
+#[derive(Serialize)]
+struct SenderComparison {
+    /// Metrics of the first sender
+    first: SenderMetrics,
+    /// Metrics of the second sender
+    second: SenderMetrics,
+}
+
+/// How often a sender mentioned a phone number
+#[derive(Serialize)]
+struct MentionCount {
+    /// The phone number's digits
+    number: String,
+    /// Sender whose name is this phone number, if any
+    name: Option<String>,
+    /// Number of times the number was @-mentioned
+    mentions: u64,
+    /// Number of times the number was written out (without an @)
+    written: u64,
+}
+
+/// A URL shared in a chat
+#[derive(Serialize)]
+struct SharedLink {
+    /// The URL
+    url: String,
+    /// Who shared the URL
+    sender: Option<String>,
+    /// When the URL was shared
+    #[serde(with = "iso8601")]
+    timestamp: NaiveDateTime,
+    /// Index of the message containing the URL in its chat
+    idx: usize,
+}
+
+/// The proportion of a chat written in a language
+#[derive(Serialize)]
+struct LanguageProportion {
+    /// ISO 639-3 code of the language
+    code: &'static str,
+    /// English name of the language
+    name: &'static str,
+    /// Fraction of the sampled messages detected as this language
+    proportion: f64,
+    /// Average detection confidence for those messages, from 0 to 1
+    confidence: f64,
+}
+
+/// Filters for `query_messages`; a message must pass every filter given to match
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct MessageQuery {
+    /// Text the message's text (or caption) must contain
+    text: Option<String>,
+    /// Whether `text` is a regular expression rather than plain text
+    regex: bool,
+    /// Whether `text` is case-sensitive
+    case_sensitive: bool,
+    /// Whether emoji should match regardless of variation selectors and skin tones (for plain text only)
+    normalize_emoji: bool,
+    /// Sender of the message; any of the "you" aliases matches all of them
+    sender: Option<String>,
+    /// Kinds the message's content must be one of
+    content_kinds: Option<Vec<ContentKind>>,
+    /// Earliest time the message may have been sent (inclusive)
+    from: Option<NaiveDateTime>,
+    /// Latest time the message may have been sent (inclusive)
+    to: Option<NaiveDateTime>,
+    /// Whether the message must be starred
+    starred_only: bool,
+    /// Lowest index the message may have (inclusive)
+    start_idx: Option<usize>,
+    /// Highest index the message may have (exclusive)
+    end_idx: Option<usize>,
+}
+
+/// A message matching a search, with where the matches are
+#[derive(Serialize)]
+struct SearchMatch {
+    /// Index of the message in its chat
+    idx: usize,
+    /// Start and end byte offsets of each match in the message's text (or caption)
+    ranges: Vec<(usize, usize)>,
+}
+
+/// The messages matching a search that were sent on one day, for `search_grouped`
+#[derive(Serialize)]
+struct SearchDay {
+    /// The (local) date
+    date: NaiveDate,
+    /// Indices of the matching messages sent that day, in order
+    indices: Vec<usize>,
+}
+
+/// A message at which the cumulative message count reached a round number
+#[derive(Serialize)]
+struct Milestone {
+    /// The cumulative count (e.g. the 1,000th message)
+    count: usize,
+    /// The message reaching the count
+    message: Message,
+}
+
+/// Number of messages sent on a day
+#[derive(Serialize)]
+struct DayCount {
+    /// The (local) date
+    date: NaiveDate,
+    /// Number of messages sent that day
+    count: u64,
+}
+
+/// A day with many messages, and what was sent on it
+#[derive(Serialize)]
+struct BusyDay {
+    /// The (local) date
+    date: NaiveDate,
+    /// Number of messages sent that day
+    count: u64,
+    /// Index of the first message sent that day, for jumping to it
+    first_idx: usize,
+    /// Number of messages each sender sent that day
+    by_sender: HashMap<String, u64>,
+    /// Number of each type of media sent that day
+    media: MediaTypeCount,
+}
+
+/// When in the day a sender is usually active, for `get_active_hours_per_sender`
+#[derive(Serialize)]
+struct ActiveHours {
+    /// Number of messages sent
+    messages: u64,
+    /// Average time of day the messages were sent, in hours from midnight (e.g. 23.5 for 11:30 PM), taking into account
+    /// that times wrap around at midnight; this is `None` if the messages are spread perfectly evenly around the clock
+    mean_hour: Option<f64>,
+    /// How closely the messages cluster around `mean_hour`, from 0 (spread evenly around the clock) to 1 (all sent at
+    /// the same time of day)
+    concentration: f64,
+}
+
+/// How regularly messages were sent
+#[derive(Serialize)]
+struct CadenceStats {
+    /// Number of days from the first message of the chat to the last (inclusive)
+    days_spanned: u64,
+    /// Number of days with at least one message
+    active_days: u64,
+    /// Average number of messages on active days
+    average_per_active_day: f64,
+    /// Most consecutive active days
+    longest_active_streak: u64,
+    /// Most consecutive days without a message
+    longest_inactive_streak: u64,
+}
+
+/// A run of consecutive days with at least one message each
+#[derive(Clone, Copy, Serialize)]
+struct Streak {
+    /// First day of the streak
+    start: NaiveDate,
+    /// Last day of the streak
+    end: NaiveDate,
+    /// Number of days in the streak
+    days: u64,
+}
+
+/// The streaks of consecutive active days of a chat, for `get_streaks`
+#[derive(Serialize)]
+struct Streaks {
+    /// The longest streak (the earliest, if there are several); this is only `None` if there are no messages
+    longest: Option<Streak>,
+    /// The streak ending on the day of the last message; this is only `None` if there are no messages
+    current: Option<Streak>,
+}
+
+/// How regularly messages were sent in a chat, overall and by each sender
+#[derive(Serialize)]
+struct CadenceReport {
+    /// Stats for all messages
+    overall: CadenceStats,
+    /// Stats for each sender's messages
+    by_sender: HashMap<String, CadenceStats>,
+}
+
+/// The proportion of media to text messages over a period of time
+#[derive(Serialize)]
+struct MediaRatio {
+    /// First day of the period
+    start: NaiveDate,
+    /// Number of media messages sent in the period
+    media: u64,
+    /// Number of text messages sent in the period
+    text: u64,
+    /// Fraction of the media and text messages that were media; this is `None` if neither were sent
+    media_fraction: Option<f64>,
+}
+
+/// Amount of text written
+#[derive(Default, Serialize)]
+struct TextVolume {
+    /// Number of (whitespace-separated) words
+    words: u64,
+    /// Number of characters
+    characters: u64,
+}
+
+/// Amount of text written over a period of time
+#[derive(Serialize)]
+struct VolumePeriod {
+    /// First day of the period
+    start: NaiveDate,
+    /// Amount of text written by everyone
+    total: TextVolume,
+    /// Amount of text written by each sender
+    by_sender: HashMap<String, TextVolume>,
+}
+
+/// How much a sender starts conversations rather than replying, for `get_initiative_balance`
+#[derive(Default, Serialize)]
+struct SenderInitiative {
+    /// Number of conversations the sender sent the first message of
+    initiated: u64,
+    /// Number of conversations the sender sent messages in, but didn't start
+    responded: u64,
+    /// `initiated` divided by `responded`; this is `None` if the sender never only responded
+    initiative_ratio: Option<f64>,
+    /// Number of the sender's messages that replied to someone else's message in the same conversation
+    replies: u64,
+    /// Average time (in minutes) between someone else's message and the sender's reply; this is `None` if there were
+    /// no replies
+    average_reply_minutes: Option<f64>,
+}
+
+/// Who starts conversations and who responds in a chat
+///
+/// Messages (other than system messages) are split into conversations wherever more than `conversation_gap_minutes`
+/// pass without a message. Whoever sent the first message of a conversation initiated it, and everyone else who
+/// sent a message in it responded. A reply is a message following a message from someone else in the same
+/// conversation, so reply times are never longer than the conversation gap.
+#[derive(Serialize)]
+struct InitiativeBalance {
+    /// Gap used to split conversations
+    conversation_gap_minutes: i64,
+    /// Number of conversations
+    conversations: u64,
+    /// Stats for each sender; all of the "you" aliases are counted under the first one
+    by_sender: HashMap<String, SenderInitiative>,
+}
+
+/// One sender's share of a chat, for `get_balance`
+#[derive(Default, Serialize)]
+struct SenderBalance {
+    /// Number of messages sent
+    messages: u64,
+    /// Number of characters written in text messages and captions
+    characters: u64,
+    /// Fraction of all messages sent by the sender
+    message_share: f64,
+    /// Fraction of all characters written by the sender
+    character_share: f64,
+}
+
+/// How evenly the messages of a chat are split between its senders
+#[derive(Serialize)]
+struct Balance {
+    /// Total number of messages, excluding system messages
+    messages: u64,
+    /// Total number of characters written in text messages and captions
+    characters: u64,
+    /// How close to an even split the messages are between two senders, from 0 (one sender sent every message) to 1
+    /// (both sent the same number); this is `None` unless the chat has exactly two senders
+    reciprocity: Option<f64>,
+    /// Each sender's share; all of the "you" aliases are counted under the first one
+    by_sender: HashMap<String, SenderBalance>,
+}
+
+/// The longest message of a chat, for `get_longest_message`
+#[derive(Serialize)]
+struct LongestMessage {
+    /// Length of the message in graphemes
+    length: usize,
+    /// The message itself
+    message: Message,
+}
+
+/// How many text messages fall into each range of lengths, for `get_length_histogram`
+///
+/// Bucket `i` holds messages of `edges[i - 1] + 1` to `edges[i]` characters (starting from 1 for the first bucket),
+/// and the final bucket holds every message longer than the last edge, so there is one more count than there are edges.
+#[derive(Serialize)]
+struct LengthHistogram {
+    /// Upper bounds (inclusive, in characters) of the buckets
+    edges: Vec<usize>,
+    /// Counts for every sender together
+    overall: Vec<u64>,
+    /// Counts for each sender; all of the "you" aliases are counted under the first one
+    by_sender: HashMap<String, Vec<u64>>,
+}
+
+/// Who a sender's messages follow, for `get_reply_targets`
+#[derive(Default, Serialize)]
+struct ReplyTargets {
+    /// Number of messages following the sender's own previous message
+    to_self: u64,
+    /// Number of messages following someone else's message
+    to_other: u64,
+    /// Fraction of the sender's messages (other than the first of the chat) that followed their own message
+    self_fraction: f64,
+}
+
+/// Messages sent in a single year
+#[derive(Serialize)]
+struct YearMessages {
+    /// The year
+    year: i32,
+    /// Messages sent that year
+    messages: Vec<Message>,
+}
+
+/// The sender with the most messages in a month
+#[derive(Serialize)]
+struct MonthlyTopSender {
+    /// The year
+    year: i32,
+    /// The month (1-12)
+    month: u32,
+    /// Sender with the most messages that month (the alphabetically first one, if tied)
+    sender: String,
+    /// Number of messages `sender` sent that month
+    count: u64,
+    /// Other senders who sent as many messages as `sender` that month
+    tied_with: Vec<String>,
+}
+
+/// Counts of positive and negative lexicon matches
+#[derive(Serialize, Default)]
+struct SentimentScore {
+    /// Number of positive words and emoji
+    positive: u64,
+    /// Number of negative words and emoji
+    negative: u64,
+    /// Fraction of the matches that were positive, if there were any
+    positivity: Option<f64>,
+}
+
+impl SentimentScore {
+    /// Adds the matches from a single message
+    fn add(&mut self, positive: u64, negative: u64) {
+        self.positive += positive;
+        self.negative += negative;
+        let total = self.positive + self.negative;
+        self.positivity = if total == 0 {
+            None
+        } else {
+            Some(self.positive as f64 / total as f64)
+        };
+    }
+}
+
+/// Sentiment score for a single month
+#[derive(Serialize)]
+struct MonthSentiment {
+    /// The year
+    year: i32,
+    /// The month (1-12)
+    month: u32,
+    /// Score for that month
+    score: SentimentScore,
+}
+
+/// Heuristic estimate of a chat's "mood"
+#[derive(Serialize)]
+struct SentimentEstimate {
+    /// Score across the whole chat
+    overall: SentimentScore,
+    /// Score for each sender
+    by_sender: HashMap<String, SentimentScore>,
+    /// Score for each month, in chronological order
+    by_month: Vec<MonthSentiment>,
+}
+
+/// Whether a chat is between two people or a group
+#[derive(Serialize, Clone, Copy)]
+enum ChatKind {
+    /// A direct message between two people
+    DIRECT,
+    /// A group chat with three or more people
+    GROUP,
+}
+
+/// The participants of a chat
+#[derive(Serialize)]
+struct ChatType {
+    /// Whether the chat is a direct message or a group
+    kind: ChatKind,
+    /// Number of people who have sent a message
+    participant_count: usize,
+    /// People who have sent a message, sorted alphabetically
+    participants: Vec<String>,
+}
+
+/// A chat from the frontend to load
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+struct ChatToLoad {
+    /// Unique chat ID
+    id: Uuid,
+    /// Chat file path
+    file: String,
+    /// Chat resource directory path
+    directory: Option<String>,
+    /// Chat name
+    name: String,
+    /// Indices of the starred messages
+    starred: Vec<usize>,
+    /// Which senders are "you"
+    #[serde(default, deserialize_with = "deserialize_you")]
+    you: Vec<String>,
+    /// Offset (in minutes) to shift the exported timestamps by, if any
+    tz_offset_minutes: Option<i32>,
+    /// Display color chosen for each sender
+    #[serde(default)]
+    sender_colors: HashMap<String, String>,
+    /// Whether senders whose names differ only in case should be treated as the same person
+    #[serde(default)]
+    merge_sender_case: bool,
+    /// Patterns identifying automated messages (such as business greetings and away messages)
+    #[serde(default)]
+    automated_patterns: Vec<String>,
+    /// Export version to parse the chat as; it's detected from the first line if not given
+    #[serde(default)]
+    format_hint: Option<ExportVersion>,
+    /// Label of the encoding to read the chat file with (such as `windows-1252`); if not given, it's UTF-8 (or UTF-16
+    /// if the file starts with a byte order mark), with lines that aren't valid UTF-8 read as Windows-1252
+    #[serde(default)]
+    encoding: Option<String>,
+    /// Index of the last message that has been read, if any
+    #[serde(default)]
+    last_read_idx: Option<usize>,
+    /// Gap (in minutes) between messages after which a new conversation starts, if it's been set for this chat
+    #[serde(default)]
+    gap_threshold_minutes: Option<i64>,
+    /// Size (in bytes) above which the file is only parsed if its first line looks like a chat line; defaults to
+    /// `DEFAULT_MAX_CHAT_FILE_SIZE`
+    #[serde(default)]
+    max_file_size: Option<u64>,
+}
+
+/// Progress of loading a chat file
+#[derive(Clone, Serialize)]
+struct LoadProgress {
+    /// Name of the chat being loaded
+    chat: String,
+    /// Number of bytes of the chat file read so far
+    bytes_read: u64,
+    /// Total number of bytes in the chat file
+    total_bytes: u64,
+}
+
+/// A batch of results from a streaming search
+#[derive(Clone, Serialize)]
+struct SearchBatch {
+    /// ID of the search the results are for
+    search_id: u64,
+    /// Indices of the matching messages
+    indices: Vec<usize>,
+}
+
+/// Notice that a streaming search has finished
+#[derive(Clone, Serialize)]
+struct SearchComplete {
+    /// ID of the search that finished
+    search_id: u64,
+    /// Whether the search was cancelled before scanning the whole chat
+    cancelled: bool,
+}
+
+struct ParsedWhatsAppChat {
+    /// Warning messages during loading
+    warnings: Vec<String>,
+    /// Parsed chat
+    chat: WhatsAppChat,
+}
+
+/// What a cached parsed chat was parsed from; if any of it has changed, the chat needs to be parsed again
+#[derive(Serialize, Deserialize, PartialEq)]
+struct ChatCacheKey {
+    /// Version of the cache format (see `CHAT_CACHE_VERSION`)
+    cache_version: u32,
+    /// Version of the app, since parsing may have changed since
+    app_version: String,
+    /// When the chat file was last modified
+    modified: SystemTime,
+    /// Size of the chat file in bytes
+    size: u64,
+    /// Media directory
+    directory: Option<String>,
+    /// When the media directory was last modified, which changes when files are added to it, removed from it or
+    /// renamed
+    directory_modified: Option<SystemTime>,
+    /// Which senders were "you"
+    you: Vec<String>,
+    /// Offset (in minutes) the timestamps were shifted by, if any
+    tz_offset_minutes: Option<i32>,
+    /// Whether senders whose names differ only in case were merged
+    merge_sender_case: bool,
+    /// Patterns identifying automated messages
+    automated_patterns: Vec<String>,
+    /// Export version the chat was parsed as, if it was given
+    format_hint: Option<ExportVersion>,
+    /// Label of the encoding the chat file was read with, if it was given
+    encoding: Option<String>,
+}
+
+/// The results of parsing a chat, as cached (after a `ChatCacheKey`) by `write_chat_cache`
+#[derive(Serialize, Deserialize)]
+struct ChatCache {
+    /// Parsed messages
+    messages: Vec<Message>,
+    /// Lines of the chat file each message was parsed from, which aren't included when serializing messages
+    source_lines: Vec<Range<usize>>,
+    /// Precision of the exported timestamps
+    timestamp_precision: TimestampPrecision,
+    /// Format the message timestamps were parsed with
+    timestamp_format: String,
+    /// Warnings from parsing the chat
+    warnings: Vec<String>,
+}
+
+/// Maintains the app state
+struct AppState {
+    /// Mapping of chat names to chat objects
+    chats: Mutex<Vec<Arc<WhatsAppChat>>>,
+    /// App theme
+    theme: Mutex<Theme>,
+    /// ID of the latest streaming search; any search with an older ID stops early
+    search_id: AtomicU64,
+    /// Whether the chats currently being loaded should stop loading
+    cancel_load: AtomicBool,
+    /// Whether the chat currently being parsed again should stop parsing; it's separate from `cancel_load` so that
+    /// starting to parse a chat again doesn't undo cancelling a load
+    cancel_reparse: AtomicBool,
+}
+
+impl WhatsAppChat {
+    /// Creates a chat from its parsed messages
+    /// # Parameters
+    /// * `chat` - Chat that was loaded
+    /// * `messages` - Parsed messages, in order
+    /// * `timestamp_precision` - Precision of the exported timestamps
+    /// * `timestamp_format` - Format the message timestamps were parsed with
+    fn from_parsed(
+        chat: &ChatToLoad,
+        messages: Vec<Message>,
+        timestamp_precision: TimestampPrecision,
+        timestamp_format: String,
+    ) -> WhatsAppChat {
+        let anchors = message_anchors(&messages);
+        WhatsAppChat {
+            id: chat.id,
+            messages,
+            file: chat.file.clone(),
+            directories: match &chat.directory {
+                Some(d) => vec![d.clone()],
+                None => Vec::new(),
+            },
+            name: chat.name.clone(),
+            you: Arc::new(Mutex::new(chat.you.clone())),
+            tz_offset_minutes: chat.tz_offset_minutes,
+            anchors,
+            sender_colors: Arc::new(Mutex::new(chat.sender_colors.clone())),
+            merge_sender_case: chat.merge_sender_case,
+            automated_patterns: chat.automated_patterns.clone(),
+            format_hint: chat.format_hint,
+            encoding: chat.encoding.clone(),
+            last_read_idx: Arc::new(Mutex::new(chat.last_read_idx)),
+            gap_threshold_minutes: Arc::new(Mutex::new(chat.gap_threshold_minutes)),
+            timestamp_precision,
+            timestamp_format,
+            counts_cache: SenderCountsCache::default(),
+            revision: Arc::new(AtomicU64::new(NEXT_REVISION.fetch_add(1, Relaxed))),
+            stars_revision: Arc::new(AtomicU64::new(NEXT_REVISION.fetch_add(1, Relaxed))),
+        }
+    }
+
+    /// Gets a summary of the chat, without any warnings
+    fn summary(&self) -> Result<ChatSummary, String> {
+        let you = self.you.lock().or(Err("Failed to get lock on you"))?;
+        let (media_resolved, media_unresolved, media_omitted) = self.count_media_resolution();
+        Ok(ChatSummary {
+            warnings: Vec::new(),
+            id: self.id,
+            name: self.name.clone(),
+            first_sent: self.messages.iter().map(|m| m.timestamp).min(),
+            last_sent: self.messages.iter().map(|m| m.timestamp).max(),
+            last_message: self.messages.last().cloned(),
+            number_of_messages: self.messages.len(),
+            media_resolved,
+            media_unresolved,
+            media_omitted,
+            starred: self
+                .messages
+                .iter()
+                .filter_map(|m| {
+                    if m.starred.load(Relaxed) {
+                        Some(m.clone())
+                    } else {
+                        None
+                    }
+                })
+                .collect(),
+            you: you.clone(),
+            tz_offset_minutes: self.tz_offset_minutes,
+            last_read_idx: *self
+                .last_read_idx
+                .lock()
+                .or(Err("Failed to get lock on last read"))?,
+        })
+    }
+
+    /// Gets the positions of the media messages whose resolved path no longer exists (e.g. because the file was moved)
+    fn missing_media(&self) -> Vec<usize> {
+        self.messages
+            .iter()
+            .enumerate()
+            .filter_map(|(i, m)| match &m.content {
+                MessageContent::Media(Media {
+                    path: Some(path), ..
+                }) if !Path::new(path).exists() => Some(i),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Gets a copy of the chat with the paths of the media messages at `positions` cleared, marking them as unresolved
+    fn without_media_paths(&self, positions: &[usize]) -> WhatsAppChat {
+        let mut new_chat = self.clone();
+        new_chat.bump_revision();
+        for &i in positions {
+            if let MessageContent::Media(media) = &mut new_chat.messages[i].content {
+                media.path = None;
+            }
+        }
+        new_chat
+    }
+
+    /// Gets a copy of the chat for display with the senders that are phone numbers redacted (see
+    /// `redact_phone_number`)
+    ///
+    /// The copy has its own "you" and sender colors, so changing them doesn't affect the saved chat.
+    fn with_redacted_numbers(&self) -> WhatsAppChat {
+        let you = self.you.lock().map(|y| y.clone()).unwrap_or_default();
+        let sender_colors = self
+            .sender_colors
+            .lock()
+            .map(|c| c.clone())
+            .unwrap_or_default();
+        WhatsAppChat {
+            messages: self
+                .messages
+                .iter()
+                .map(Message::with_redacted_sender)
+                .collect(),
+            you: Arc::new(Mutex::new(
+                you.iter().map(|y| redact_phone_number(y)).collect(),
+            )),
+            sender_colors: Arc::new(Mutex::new(
+                sender_colors
+                    .into_iter()
+                    .map(|(sender, color)| (redact_phone_number(&sender), color))
+                    .collect(),
+            )),
+            ..self.clone()
+        }
+    }
+
+    /// Records that the messages have changed
+    fn bump_revision(&self) {
+        self.revision
+            .store(NEXT_REVISION.fetch_add(1, Relaxed), Relaxed);
+    }
+
+    /// Gets the revision of the messages and which of them are starred, which increases whenever either changes
+    fn combined_revision(&self) -> u64 {
+        self.revision
+            .load(Relaxed)
+            .max(self.stars_revision.load(Relaxed))
+    }
+
+    /// Stars the message at `idx` if it isn't starred, and unstars it if it is
+    fn toggle_star(&self, idx: usize) -> Result<(), String> {
+        let Some(message) = self.messages.get(idx) else {
+            return Err("No message exists at that index".to_owned());
+        };
+        message.starred.fetch_not(Relaxed);
+        self.stars_revision
+            .store(NEXT_REVISION.fetch_add(1, Relaxed), Relaxed);
+        Ok(())
+    }
+
+    /// Gets the starred messages, each with its current index
+    fn starred_messages(&self) -> Vec<Message> {
+        self.messages
+            .iter()
+            .filter(|m| m.starred.load(Relaxed))
+            .map(|m| m.clone())
+            .collect()
+    }
+
+    /// Gets the gap (in minutes) between messages after which a new conversation starts, which is
+    /// `DEFAULT_CONVERSATION_GAP_MINUTES` unless it's been set for this chat
+    fn gap_threshold(&self) -> i64 {
+        self.gap_threshold_minutes
+            .lock()
+            .ok()
+            .and_then(|g| *g)
+            .unwrap_or(DEFAULT_CONVERSATION_GAP_MINUTES)
+    }
+
+    /// Checks whether this is the chat with the given ID or, if no ID is given, the given name
+    ///
+    /// The ID doesn't change when a chat is renamed, so it's preferred when available.
+    fn is(&self, name: &str, id: Option<Uuid>) -> bool {
+        match id {
+            Some(id) => self.id == id,
+            None => self.name == name,
+        }
+    }
+
+    /// Gets the number of media messages that were resolved, unresolved, and omitted from the export, in that order
+    fn count_media_resolution(&self) -> (u64, u64, u64) {
+        let mut counts = (0, 0, 0);
+        for m in self.messages.iter() {
+            if let MessageContent::Media(media) = &m.content {
+                match (&media.path, &media.file_name) {
+                    (Some(_), _) => counts.0 += 1,
+                    (None, Some(_)) => counts.1 += 1,
+                    (None, None) => counts.2 += 1,
+                }
+            }
+        }
+        counts
+    }
+
+    /// Gets the number of messages sent by each person in the chat broken down by type
+    ///
+    /// All of the "you" aliases are counted under the first one. This is a memo cache: all of the messages are counted,
+    /// and the counts are reused until "you" or the messages (see `revision`) change.
+    fn count_by_sender(&self) -> HashMap<String, MessageTypeCount> {
+        let you = self.you.lock().map(|y| y.clone()).unwrap_or_default();
+        let revision = self.revision.load(Relaxed);
+        let mut cache = match self.counts_cache.0.lock() {
+            Ok(cache) => cache,
+            Err(_) => {
+                let mut counts = HashMap::new();
+                WhatsAppChat::add_counts(&mut counts, &self.messages, &you);
+                return counts;
+            }
+        };
+        if let Some(cached) = cache.as_ref() {
+            if cached.you == you && cached.revision == revision {
+                return cached.counts.clone();
+            }
+        }
+        let mut counts = HashMap::new();
+        WhatsAppChat::add_counts(&mut counts, &self.messages, &you);
+        *cache = Some(CachedSenderCounts {
+            you,
+            revision,
+            counts: counts.clone(),
+        });
+        counts
+    }
+
+    /// Gets the `n` days with the most messages, busiest first
+    fn busiest_days(&self, n: usize) -> Vec<BusyDay> {
+        let you = self.you.lock().map(|y| y.clone()).unwrap_or_default();
+        let mut days: HashMap<NaiveDate, BusyDay> = HashMap::new();
+        for m in self.messages.iter() {
+            let date = m.timestamp.date();
+            let day = days.entry(date).or_insert_with(|| BusyDay {
+                date,
+                count: 0,
+                first_idx: m.idx,
+                by_sender: HashMap::new(),
+                media: MediaTypeCount::default(),
+            });
+            day.count += 1;
+            if let Some(s) = &m.sender {
+                let s = if you.contains(s) { &you[0] } else { s };
+                *day.by_sender.entry(s.clone()).or_insert(0) += 1;
+            }
+            if let MessageContent::Media(media) = &m.content {
+                match media.media_type {
+                    _ if media.voice_note => day.media.voice_note += 1,
+                    MediaType::PHOTO => day.media.photo += 1,
+                    MediaType::VIDEO => day.media.video += 1,
+                    MediaType::AUDIO => day.media.audio += 1,
+                    MediaType::OTHER => day.media.other += 1,
+                }
+            }
+        }
+        let mut days: Vec<BusyDay> = days.into_values().collect();
+        // Earlier days win ties
+        days.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.date.cmp(&b.date)));
+        days.truncate(n);
+        days
+    }
+
+    /// Adds the given messages to per-sender counts of each message type
+    /// # Parameters
+    /// * `counts` - Counts to add to
+    /// * `messages` - Messages to count
+    /// * `you` - Which senders are "you"; they're all counted under the first one
+    fn add_counts(
+        counts: &mut HashMap<String, MessageTypeCount>,
+        messages: &[Message],
+        you: &[String],
+    ) {
+        messages.iter().for_each(|m| {
+            if let Some(s) = &m.sender {
+                let s = if you.contains(s) { &you[0] } else { s };
+                let mtc = counts.entry(s.clone()).or_default();
+                match &m.content {
+                    MessageContent::Text(_) if m.automated => mtc.automated += 1,
+                    MessageContent::Text(_) => mtc.text += 1,
+                    MessageContent::System(_) => mtc.system += 1,
+                    MessageContent::Deleted(_) => mtc.deleted += 1,
+                    MessageContent::Media(mm) => {
+                        if mm.caption.is_some() {
+                            mtc.captioned_media += 1;
+                        }
+                        match mm.media_type {
+                            _ if mm.voice_note => mtc.media.voice_note += 1,
+                            MediaType::PHOTO => mtc.media.photo += 1,
+                            MediaType::VIDEO => mtc.media.video += 1,
+                            MediaType::AUDIO => mtc.media.audio += 1,
+                            MediaType::OTHER => mtc.media.other += 1,
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Saves basic information about the specified chats
+/// # Parameters
+/// * `directory` - Directory to which the chat information should be saved
+/// * `chats` - Chats to save
+fn save_basic_chat_data(
+    directory: &PathBuf,
+    chats: &Vec<Arc<WhatsAppChat>>,
+    theme: Theme,
+) -> Result<(), String> {
+    create_dir_all(directory).map_err(|err| err.to_string())?;
+    let mut basic_data = Vec::with_capacity(chats.len());
+    for c in chats {
+        let you = c
+            .you
+            .lock()
+            .or(Err("Failed to get lock on state".to_owned()))?
+            .clone();
+        let sender_colors = c
+            .sender_colors
+            .lock()
+            .or(Err("Failed to get lock on state".to_owned()))?
+            .clone();
+        let last_read_idx = *c
+            .last_read_idx
+            .lock()
+            .or(Err("Failed to get lock on state".to_owned()))?;
+        let gap_threshold_minutes = *c
+            .gap_threshold_minutes
+            .lock()
+            .or(Err("Failed to get lock on state".to_owned()))?;
+        basic_data.push(BasicChatDataWithStars {
+            id: c.id,
+            file: c.file.clone(),
+            directory: c.directories.first().cloned(),
+            name: c.name.clone(),
+            starred: c
+                .messages
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, m)| {
+                    if m.starred.load(Relaxed) {
+                        Some(idx)
+                    } else {
+                        None
+                    }
+                })
+                .collect(),
+            you,
+            tz_offset_minutes: c.tz_offset_minutes,
+            sender_colors,
+            merge_sender_case: c.merge_sender_case,
+            automated_patterns: c.automated_patterns.clone(),
+            format_hint: c.format_hint,
+            encoding: c.encoding.clone(),
+            last_read_idx,
+            gap_threshold_minutes,
+        });
+    }
+    // The recently viewed chats are kept, other than any that were removed
+    let recent = read_saved_chats(directory)
+        .map(|s| s.recent)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|id| chats.iter().any(|c| c.id == *id))
+        .collect();
+    write_saved_chats(
+        directory,
+        &SavedChats {
+            version: SAVE_VERSION,
+            theme,
+            chats: basic_data,
+            recent,
+            warnings: Vec::new(),
+        },
+    )
+}
+
+/// Reads the saved chat information from `directory`, if there is any and it can be read
+fn read_saved_chats(directory: &Path) -> Option<SavedChats> {
+    let data = fs::read_to_string(directory.join(SAVE_NAME)).ok()?;
+    serde_json::from_str(&data).ok()
+}
+
+/// Records that the chat with the given ID was viewed, moving it to the front of the recently viewed chats
+/// # Parameters
+/// * `directory` - Directory the chat information is saved in
+/// * `id` - ID of the chat
+fn record_recent_chat(directory: &Path, id: Uuid) -> Result<(), String> {
+    let Some(mut saved) = read_saved_chats(directory) else {
+        return Ok(());
+    };
+    if saved.recent.first() == Some(&id) {
+        return Ok(());
+    }
+    saved.recent.retain(|r| *r != id);
+    saved.recent.insert(0, id);
+    saved.recent.truncate(RECENT_CHATS_MAX);
+    write_saved_chats(directory, &saved)
+}
+
+/// Writes the saved chat information to `directory`
+///
+/// The data is written to a temporary file that then replaces the existing file, so an interrupted write can't corrupt it.
+/// # Parameters
+/// * `directory` - Directory to which the chat information should be saved
+/// * `saved` - Information to save
+fn write_saved_chats(directory: &Path, saved: &SavedChats) -> Result<(), String> {
+    let data = serde_json::to_vec(saved).map_err(|e| e.to_string())?;
+    let temp_path = directory.join(format!("{0}.tmp", SAVE_NAME));
+    let mut f = File::create(&temp_path).map_err(|e| e.to_string())?;
+    f.write_all(&data)
+        .and_then(|_| f.sync_all())
+        .map_err(|e| e.to_string())?;
+    fs::rename(&temp_path, directory.join(SAVE_NAME)).map_err(|e| e.to_string())
+}
+
+/// Checks whether `c` is an emoji variation selector or skin tone modifier
+fn is_emoji_modifier(c: char) -> bool {
+    matches!(c, '\u{FE0E}' | '\u{FE0F}' | '\u{1F3FB}'..='\u{1F3FF}')
+}
+
+/// Checks whether `c` is an emoji (not counting modifiers)
+fn is_emoji(c: char) -> bool {
+    matches!(c, '\u{1F300}'..='\u{1FAFF}' | '\u{2600}'..='\u{27BF}' | '\u{1F1E6}'..='\u{1F1FF}')
+}
+
+/// Removes emoji variation selectors and skin tone modifiers, so that e.g. "❤️" and "❤" compare equal
+fn strip_emoji_modifiers(text: &str) -> String {
+    text.chars().filter(|c| !is_emoji_modifier(*c)).collect()
+}
+
+/// Gets the text of a message that is searched: the text itself, a media caption, or the system message
+fn searchable_text(content: &MessageContent) -> Option<&str> {
+    match content {
+        MessageContent::Text(text) => Some(text),
+        MessageContent::Media(media) => media.caption.as_deref(),
+        MessageContent::System(system) => Some(system),
+        MessageContent::Deleted(_) => None,
+    }
+}
+
+/// Finds where `search` occurs in `text`, ignoring case
+///
+/// Returns the byte ranges of the matches in the original `text`. Since lowercasing can change the length of a
+/// character, each lowercased byte is mapped back to the character it came from.
+/// # Parameters
+/// * `text` - Text to search
+/// * `search` - String to search for
+/// * `normalize_emoji` - Whether emoji variants should be matched (see `strip_emoji_modifiers`)
+fn match_offsets(text: &str, search: &str, normalize_emoji: bool) -> Vec<(usize, usize)> {
+    let lower_search: String = search
+        .chars()
+        .filter(|c| !(normalize_emoji && is_emoji_modifier(*c)))
+        .flat_map(char::to_lowercase)
+        .collect();
+    if lower_search.is_empty() {
+        return Vec::new();
+    }
+    let mut lower = String::with_capacity(text.len());
+    // Start and end of the original character for each byte of `lower`
+    let mut original_ranges = Vec::with_capacity(text.len());
+    for (i, c) in text.char_indices() {
+        if normalize_emoji && is_emoji_modifier(c) {
+            continue;
+        }
+        for lc in c.to_lowercase() {
+            lower.push(lc);
+            for _ in 0..lc.len_utf8() {
+                original_ranges.push((i, i + c.len_utf8()));
+            }
+        }
+    }
+    lower
+        .match_indices(&lower_search)
+        .map(|(start, m)| {
+            (
+                original_ranges[start].0,
+                original_ranges[start + m.len() - 1].1,
+            )
+        })
+        .collect()
+}
+
+/// Prepares a search string for use with `message_matches`
+/// # Parameters
+/// * `search` - String to search for
+/// * `normalize_emoji` - Whether emoji variants should be matched (see `strip_emoji_modifiers`)
+fn prepare_search(search: &str, normalize_emoji: bool) -> String {
+    if normalize_emoji {
+        strip_emoji_modifiers(&search.to_lowercase())
+    } else {
+        search.to_lowercase()
+    }
+}
+
+/// Checks whether a message contains the search string
+/// # Parameters
+/// * `message` - Message to check
+/// * `lower_search` - String to search for, as returned by `prepare_search`
+/// * `normalize_emoji` - Whether emoji variants should be matched
+fn message_matches(message: &Message, lower_search: &str, normalize_emoji: bool) -> bool {
+    match searchable_text(&message.content) {
+        Some(text) if normalize_emoji => {
+            strip_emoji_modifiers(&text.to_lowercase()).contains(lower_search)
+        }
+        Some(text) => text.to_lowercase().contains(lower_search),
+        None => false,
+    }
+}
+
+/// Checks whether a message contains every word of the search string, treating a media message as its type (such as
+/// "photo") followed by its caption
+///
+/// This lets a search such as "photo cake" find a photo captioned "the cake".
+/// # Parameters
+/// * `message` - Message to check
+/// * `lower_search` - String to search for, as returned by `prepare_search`
+/// * `normalize_emoji` - Whether emoji variants should be matched
+fn message_matches_with_media_type(
+    message: &Message,
+    lower_search: &str,
+    normalize_emoji: bool,
+) -> bool {
+    let text = match &message.content {
+        MessageContent::Media(media) => {
+            let label = match media.media_type {
+                _ if media.voice_note => "voice note",
+                MediaType::PHOTO => "photo",
+                MediaType::VIDEO => "video",
+                MediaType::AUDIO => "audio",
+                MediaType::OTHER => "file",
+            };
+            match &media.caption {
+                Some(caption) => format!("{0} {1}", label, caption),
+                None => label.to_owned(),
+            }
+        }
+        content => match searchable_text(content) {
+            Some(text) => text.to_owned(),
+            None => return false,
+        },
+    };
+    let lower = if normalize_emoji {
+        strip_emoji_modifiers(&text.to_lowercase())
+    } else {
+        text.to_lowercase()
+    };
+    let mut terms = lower_search.split_whitespace().peekable();
+    terms.peek().is_some() && terms.all(|term| lower.contains(term))
+}
+
+/// Gets a plain text representation of a message's content
+///
+/// If `redact_paths` is true, attached media is referred to by file name rather than by its full local path.
+fn content_to_string(content: &MessageContent, redact_paths: bool) -> String {
+    match content {
+        MessageContent::Text(text) => text.clone(),
+        MessageContent::System(system) => system.clone(),
+        MessageContent::Deleted(deletion) => match &deletion.deleted_by_admin {
+            Some(admin) => format!("<This message was deleted by admin {0}>", admin),
+            None if deletion.deleted_by_you => "<You deleted this message>".to_owned(),
+            None => "<This message was deleted>".to_owned(),
+        },
+        MessageContent::Media(media) => {
+            let shown_path = if redact_paths {
+                media.path.as_ref().and(media.file_name.as_ref())
+            } else {
+                media.path.as_ref()
+            };
+            let attachment = match shown_path {
+                Some(path) => format!("<attached: {0}>", path),
+                None => "<Media omitted>".to_owned(),
+            };
+            match &media.caption {
+                Some(caption) => attachment + "\n" + caption,
+                None => attachment,
+            }
+        }
+    }
+}
+
+/// Writes a single row of CSV, quoting fields as needed
+fn write_csv_row<W: Write>(writer: &mut W, fields: &[&str]) -> std::io::Result<()> {
+    let row = fields
+        .iter()
+        .map(|f| {
+            if f.contains([',', '"', '\n', '\r']) {
+                format!("\"{0}\"", f.replace('"', "\"\""))
+            } else {
+                f.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    writeln!(writer, "{0}", row)
+}
+
+/// Searches the messages in `chat` for the given string
+/// # Args
+/// * `chat` - Name of the chat to search
+/// * `search` - String to search
+/// * `content_kinds` - Kinds of messages to search (e.g. only text); all are searched if not provided
+/// * `normalize_emoji` - Whether emoji should match regardless of variation selectors and skin tones
+/// * `match_media_type` - Whether to match each word separately against the text, treating media as its type followed
+///   by its caption (see `message_matches_with_media_type`); by default the search string is matched literally
+#[tauri::command]
+fn search(
+    chat: String,
+    search: String,
+    content_kinds: Option<Vec<ContentKind>>,
+    normalize_emoji: Option<bool>,
+    match_media_type: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<Vec<usize>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    let normalize_emoji = normalize_emoji.unwrap_or(false);
+    let lower_search = prepare_search(&search, normalize_emoji);
+    let matches = if match_media_type.unwrap_or(false) {
+        message_matches_with_media_type
+    } else {
+        message_matches
+    };
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            return Ok(c
+                .messages
+                .iter()
+                .filter(|m| {
+                    ContentKind::any_matches(&content_kinds, &m.content)
+                        && matches(m, &lower_search, normalize_emoji)
+                })
+                .map(|m| m.idx)
+                .collect());
+        }
+    }
+    return Err("Failed to find chat".to_owned());
+}
+
+/// Searches the messages in `chat` for the given string, grouping the matches by the day they were sent
+///
+/// Days are in order, and days without any matches are left out.
+/// # Args
+/// * `chat` - Name of the chat to search
+/// * `search` - String to search
+/// * `content_kinds` - Kinds of messages to search (e.g. only text); all are searched if not provided
+/// * `normalize_emoji` - Whether emoji should match regardless of variation selectors and skin tones
+/// * `match_media_type` - Whether to match each word separately against the text, treating media as its type followed
+///   by its caption (see `message_matches_with_media_type`); by default the search string is matched literally
+#[tauri::command]
+fn search_grouped(
+    chat: String,
+    search: String,
+    content_kinds: Option<Vec<ContentKind>>,
+    normalize_emoji: Option<bool>,
+    match_media_type: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<Vec<SearchDay>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    let normalize_emoji = normalize_emoji.unwrap_or(false);
+    let lower_search = prepare_search(&search, normalize_emoji);
+    let matches = if match_media_type.unwrap_or(false) {
+        message_matches_with_media_type
+    } else {
+        message_matches
+    };
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let mut days: Vec<SearchDay> = Vec::new();
+            for m in c.messages.iter().filter(|m| {
+                ContentKind::any_matches(&content_kinds, &m.content)
+                    && matches(m, &lower_search, normalize_emoji)
+            }) {
+                let date = m.timestamp.date();
+                match days.last_mut() {
+                    Some(day) if day.date == date => day.indices.push(m.idx),
+                    _ => days.push(SearchDay {
+                        date,
+                        indices: vec![m.idx],
+                    }),
+                }
+            }
+            return Ok(days);
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Finds the messages in `chat` that pass all of the given filters
+///
+/// Returns the indices of the matching messages, in order. If no filters are given, every message matches.
+/// # Args
+/// * `chat` - Name of the chat to search
+/// * `query` - Filters the messages must pass
+#[tauri::command]
+fn query_messages(
+    chat: String,
+    query: MessageQuery,
+    state: State<'_, AppState>,
+) -> Result<Vec<usize>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    let c = locked_chats
+        .iter()
+        .find(|c| c.name == chat)
+        .ok_or("Failed to find chat".to_owned())?;
+    // Messages are in chronological order, so the index and time filters just narrow the range of messages to check
+    let mut start = query.start_idx.unwrap_or(0);
+    let mut end = query.end_idx.unwrap_or(usize::MAX).min(c.messages.len());
+    if let Some(from) = query.from {
+        start = start.max(c.messages.partition_point(|m| m.timestamp < from));
+    }
+    if let Some(to) = query.to {
+        end = end.min(c.messages.partition_point(|m| m.timestamp <= to));
+    }
+    if start >= end {
+        return Ok(Vec::new());
+    }
+    // Every message in the range matches if there are no other filters
+    if query.text.is_none()
+        && query.sender.is_none()
+        && query.content_kinds.is_none()
+        && !query.starred_only
+    {
+        return Ok((start..end).collect());
+    }
+    let you = c.you.lock().map(|y| y.clone()).unwrap_or_default();
+    let senders = match &query.sender {
+        Some(s) if you.contains(s) => Some(you),
+        Some(s) => Some(vec![s.clone()]),
+        None => None,
+    };
+    let text_matches: Box<dyn Fn(&Message) -> bool> = match &query.text {
+        None => Box::new(|_| true),
+        Some(text) if query.regex => {
+            let re = RegexBuilder::new(text)
+                .case_insensitive(!query.case_sensitive)
+                .build()
+                .map_err(|e| e.to_string())?;
+            Box::new(move |m| searchable_text(&m.content).is_some_and(|t| re.is_match(t)))
+        }
+        Some(text) if query.case_sensitive => {
+            let text = text.clone();
+            Box::new(move |m| searchable_text(&m.content).is_some_and(|t| t.contains(&text)))
+        }
+        Some(text) => {
+            let lower_search = prepare_search(text, query.normalize_emoji);
+            let normalize_emoji = query.normalize_emoji;
+            Box::new(move |m| message_matches(m, &lower_search, normalize_emoji))
+        }
+    };
+    Ok(c.messages[start..end]
+        .iter()
+        .filter(|m| {
+            if query.starred_only && !m.starred.load(Relaxed) {
+                return false;
+            }
+            if !ContentKind::any_matches(&query.content_kinds, &m.content) {
+                return false;
+            }
+            if let Some(senders) = &senders {
+                if !m.sender.as_ref().is_some_and(|s| senders.contains(s)) {
+                    return false;
+                }
+            }
+            text_matches(m)
+        })
+        .map(|m| m.idx)
+        .collect())
+}
+
+/// Searches the messages in `chat` for the given string in the background, emitting the results as they're found
+///
+/// Matching message indices are emitted in batches as `search_results` events, followed by a `search_complete` event.
+/// Starting another streaming search (or calling `cancel_search`) cancels this one. Returns the ID of the search, which
+/// is included in its events.
+/// # Args
+/// * `chat` - Name of the chat to search
+/// * `search` - String to search
+/// * `content_kinds` - Kinds of messages to search; all are searched if not provided
+/// * `normalize_emoji` - Whether emoji should match regardless of variation selectors and skin tones
+/// * `match_media_type` - Whether to match each word separately, treating media as its type followed by its caption
+///   (see `message_matches_with_media_type`)
+#[tauri::command]
+fn search_stream(
+    chat: String,
+    search: String,
+    content_kinds: Option<Vec<ContentKind>>,
+    normalize_emoji: Option<bool>,
+    match_media_type: Option<bool>,
+    state: State<'_, AppState>,
+    handle: AppHandle,
+) -> Result<u64, String> {
+    let c = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?
+        .iter()
+        .find(|c| c.name == chat)
+        .cloned()
+        .ok_or("Failed to find chat".to_owned())?;
+    let search_id = state.search_id.fetch_add(1, Relaxed) + 1;
+    let normalize_emoji = normalize_emoji.unwrap_or(false);
+    let lower_search = prepare_search(&search, normalize_emoji);
+    let matches = if match_media_type.unwrap_or(false) {
+        message_matches_with_media_type
+    } else {
+        message_matches
+    };
+    thread::spawn(move || {
+        for batch in c.messages.chunks(SEARCH_BATCH_SIZE) {
+            if handle.state::<AppState>().search_id.load(Relaxed) != search_id {
+                let _ = handle.emit(
+                    "search_complete",
+                    SearchComplete {
+                        search_id,
+                        cancelled: true,
+                    },
+                );
+                return;
+            }
+            let indices: Vec<usize> = batch
+                .iter()
+                .filter(|m| {
+                    ContentKind::any_matches(&content_kinds, &m.content)
+                        && matches(m, &lower_search, normalize_emoji)
+                })
+                .map(|m| m.idx)
+                .collect();
+            if !indices.is_empty() {
+                let _ = handle.emit("search_results", SearchBatch { search_id, indices });
+            }
+        }
+        let _ = handle.emit(
+            "search_complete",
+            SearchComplete {
+                search_id,
+                cancelled: false,
+            },
+        );
+    });
+    Ok(search_id)
+}
+
+/// Cancels the current streaming search, if any
+#[tauri::command]
+fn cancel_search(state: State<'_, AppState>) {
+    state.search_id.fetch_add(1, Relaxed);
+}
+
+/// Searches the messages in `chat` for the given string, returning where in each message it was found
+///
+/// This is the same as `search`, but also returns the byte ranges of the matches in each message's text (or caption),
+/// for highlighting.
+/// # Args
+/// * `chat` - Name of the chat to search
+/// * `search` - String to search
+/// * `content_kinds` - Kinds of messages to search (e.g. only text); all are searched if not provided
+/// * `normalize_emoji` - Whether emoji should match regardless of variation selectors and skin tones
+#[tauri::command]
+fn search_with_offsets(
+    chat: String,
+    search: String,
+    content_kinds: Option<Vec<ContentKind>>,
+    normalize_emoji: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<Vec<SearchMatch>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    let normalize_emoji = normalize_emoji.unwrap_or(false);
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            return Ok(c
+                .messages
+                .iter()
+                .filter(|m| ContentKind::any_matches(&content_kinds, &m.content))
+                .filter_map(|m| {
+                    let text = searchable_text(&m.content)?;
+                    let ranges = match_offsets(text, &search, normalize_emoji);
+                    if ranges.is_empty() {
+                        None
+                    } else {
+                        Some(SearchMatch { idx: m.idx, ranges })
+                    }
+                })
+                .collect());
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Finds the nearest message before or after a given message that matches a search, e.g. for "find next"
+///
+/// Messages are checked outward from `start`, so only as many messages as needed are searched. Returns `None` if
+/// there are no more matching messages in that direction.
+/// # Args
+/// * `chat` - Name of the chat to search
+/// * `start` - Index of the message to start from (this message itself isn't checked)
+/// * `forward` - Whether to look for the next match rather than the previous one
+/// * `search` - String to search
+/// * `regex` - Whether `search` is a (case-insensitive) regular expression rather than plain text
+#[tauri::command]
+fn find_next_match(
+    chat: String,
+    start: usize,
+    forward: bool,
+    search: String,
+    regex: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<Option<usize>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let matches: Box<dyn Fn(&Message) -> bool> = if regex.unwrap_or(false) {
+                let re = RegexBuilder::new(&search)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| e.to_string())?;
+                Box::new(move |m| searchable_text(&m.content).is_some_and(|t| re.is_match(t)))
+            } else {
+                let lower_search = prepare_search(&search, false);
+                Box::new(move |m| message_matches(m, &lower_search, false))
+            };
+            return Ok(if forward {
+                (start.saturating_add(1)..c.messages.len()).find(|&i| matches(&c.messages[i]))
+            } else {
+                (0..start.min(c.messages.len()))
+                    .rev()
+                    .find(|&i| matches(&c.messages[i]))
+            });
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Exports the messages in `chat` matching the given string, along with surrounding messages
+///
+/// The output is written as CSV if `path` ends in `.csv`, and as plain text otherwise. Returns the number of matches.
+/// # Args
+/// * `chat` - Name of the chat to search
+/// * `search` - String to search
+/// * `content_kinds` - Kinds of messages to search; all are searched if not provided
+/// * `normalize_emoji` - Whether emoji should match regardless of variation selectors and skin tones
+/// * `path` - File to write the results to
+/// * `context` - Number of messages before and after each match to include
+#[tauri::command]
+fn export_search_results(
+    chat: String,
+    search: String,
+    content_kinds: Option<Vec<ContentKind>>,
+    normalize_emoji: Option<bool>,
+    path: String,
+    context: usize,
+    state: State<'_, AppState>,
+) -> Result<usize, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    let c = locked_chats
+        .iter()
+        .find(|c| c.name == chat)
+        .ok_or("Failed to find chat".to_owned())?;
+    let normalize_emoji = normalize_emoji.unwrap_or(false);
+    let lower_search = prepare_search(&search, normalize_emoji);
+    let matches: Vec<usize> = c
+        .messages
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| {
+            ContentKind::any_matches(&content_kinds, &m.content)
+                && message_matches(m, &lower_search, normalize_emoji)
+        })
+        .map(|(i, _)| i)
+        .collect();
+    let as_csv = Path::new(&path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+    let f = File::create(&path).map_err(|e| e.to_string())?;
+    let mut writer = BufWriter::new(f);
+    if as_csv {
+        write_csv_row(&mut writer, &["timestamp", "sender", "content", "match"])
+            .map_err(|e| e.to_string())?;
+    }
+    let mut last_written: Option<usize> = None;
+    for &match_idx in matches.iter() {
+        let start = match last_written {
+            Some(last) => (last + 1).max(match_idx.saturating_sub(context)),
+            None => match_idx.saturating_sub(context),
+        };
+        let end = (match_idx + context).min(c.messages.len() - 1);
+        // Separate non-contiguous excerpts in the text output
+        if !as_csv && last_written.is_some_and(|last| start > last + 1) {
+            writeln!(writer, "...").map_err(|e| e.to_string())?;
+        }
+        for (i, m) in c.messages.iter().enumerate().take(end + 1).skip(start) {
+            let timestamp = m.timestamp.format(EXPORT_TIME_FORMAT).to_string();
+            let sender = m.sender.clone().unwrap_or_default();
+            let content = content_to_string(&m.content, false);
+            let is_match = matches.binary_search(&i).is_ok();
+            if as_csv {
+                write_csv_row(
+                    &mut writer,
+                    &[&timestamp, &sender, &content, &is_match.to_string()],
+                )
+            } else {
+                writeln!(
+                    writer,
+                    "{0}[{1}] {2}: {3}",
+                    if is_match { "* " } else { "  " },
+                    timestamp,
+                    sender,
+                    content
+                )
+            }
+            .map_err(|e| e.to_string())?;
+        }
+        last_written = Some(end.max(last_written.unwrap_or(0)));
+    }
+    writer.flush().map_err(|e| e.to_string())?;
+    Ok(matches.len())
+}
+
+/// Formats a message as a line of a plain text transcript
+/// # Args
+/// * `message` - Message to format
+/// * `sender` - Name to show as the sender (system messages have none)
+/// * `content` - Content to show (see `content_to_string`)
+fn transcript_line(message: &Message, sender: Option<&str>, content: &str) -> String {
+    let timestamp = message.timestamp.format(EXPORT_TIME_FORMAT);
+    match sender {
+        Some(sender) => format!("[{0}] {1}: {2}\n", timestamp, sender, content),
+        None => format!("[{0}] {1}\n", timestamp, content),
+    }
+}
+
+/// Gets the pseudonym for the `n`th (counting from 0) sender of an anonymized chat: Person A, Person B, ...,
+/// Person Z, Person AA, and so on
+fn pseudonym(n: usize) -> String {
+    let mut letters = Vec::new();
+    let mut n = n + 1;
+    while n > 0 {
+        n -= 1;
+        letters.push(char::from(b'A' + (n % 26) as u8));
+        n /= 26;
+    }
+    format!("Person {0}", letters.iter().rev().collect::<String>())
+}
+
+/// Replaces each occurrence of `word` in `text` that isn't part of a longer word with `replacement`
+fn replace_word(text: &str, word: &str, replacement: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut copied = 0;
+    for (pos, _) in text.match_indices(word) {
+        let before = text[..pos].chars().next_back();
+        let after = text[pos + word.len()..].chars().next();
+        if !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric) {
+            result.push_str(&text[copied..pos]);
+            result.push_str(replacement);
+            copied = pos + word.len();
+        }
+    }
+    result.push_str(&text[copied..]);
+    result
+}
+
+/// Gets the specified chat as a plain text transcript, with one `[timestamp] sender: content` line per message
+/// # Args
+/// * `chat` - Name of the chat
+/// * `redact_media_paths` - Whether attached media should be shown by file name rather than full local path
+#[tauri::command]
+fn get_plain_text(
+    chat: String,
+    redact_media_paths: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let redact_media_paths = redact_media_paths.unwrap_or(false);
+            let mut transcript = String::new();
+            for m in c.messages.iter() {
+                let content = content_to_string(&m.content, redact_media_paths);
+                transcript.push_str(&transcript_line(m, m.sender.as_deref(), &content));
+            }
+            return Ok(transcript);
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Writes the specified chat to a plain text transcript with each sender replaced by a pseudonym
+///
+/// Senders are named Person A, Person B, and so on in the order they first sent a message, and "you" is named Me.
+/// Their names are also replaced within messages. Attached media is referred to by file name only. Returns the
+/// mapping of each sender to their pseudonym.
+/// # Args
+/// * `chat` - Name of the chat
+/// * `path` - File to write the transcript to
+/// * `scrub_phone_numbers` - Whether phone numbers (see `extract_phone_numbers`) should be removed from messages
+/// * `scrub_links` - Whether links (see `extract_urls`) should be removed from messages
+#[tauri::command]
+fn export_anonymized(
+    chat: String,
+    path: String,
+    scrub_phone_numbers: Option<bool>,
+    scrub_links: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<HashMap<String, String>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    let c = locked_chats
+        .iter()
+        .find(|c| c.name == chat)
+        .ok_or("Failed to find chat".to_owned())?;
+    let you = c.you.lock().map(|y| y.clone()).unwrap_or_default();
+    let mut pseudonyms: HashMap<String, String> = HashMap::new();
+    for sender in c.messages.iter().filter_map(|m| m.sender.as_ref()) {
+        if !pseudonyms.contains_key(sender) {
+            let pseudonym = if you.contains(sender) {
+                "Me".to_owned()
+            } else {
+                pseudonym(pseudonyms.values().filter(|p| *p != "Me").count())
+            };
+            pseudonyms.insert(sender.clone(), pseudonym);
+        }
+    }
+    // Replace longer names first, so that e.g. "Ann Lee" isn't partially replaced as "Ann"
+    let mut names: Vec<&String> = pseudonyms.keys().collect();
+    names.sort_by_key(|n| Reverse(n.len()));
+    let f = File::create(&path).map_err(|e| e.to_string())?;
+    let mut writer = BufWriter::new(f);
+    for m in c.messages.iter() {
+        let mut content = content_to_string(&m.content, true);
+        for name in names.iter() {
+            content = replace_word(&content, name, &pseudonyms[*name]);
+        }
+        if scrub_links.unwrap_or(false) {
+            for url in extract_urls(&content.clone()) {
+                content = content.replace(url, "<link>");
+            }
+        }
+        if scrub_phone_numbers.unwrap_or(false) {
+            // Replace from the end so that the earlier positions stay valid
+            for (_, _, range) in extract_phone_numbers(&content.clone()).into_iter().rev() {
+                content.replace_range(range, "<phone number>");
+            }
+        }
+        let sender = m.sender.as_ref().map(|s| pseudonyms[s].as_str());
+        write!(writer, "{0}", transcript_line(m, sender, &content)).map_err(|e| e.to_string())?;
+    }
+    writer.flush().map_err(|e| e.to_string())?;
+    Ok(pseudonyms)
+}
+
+/// Stars or unstars the specified message
+/// # Args
+/// * `chat` - Name of the chat of interest
+/// * `id` - ID of the chat of interest; if given, it's used instead of `chat`
+/// * `messageIdx` - Index of the message of interest
+#[tauri::command]
+#[allow(non_snake_case)]
+fn star_message(
+    chat: String,
+    id: Option<Uuid>,
+    messageIdx: usize,
+    state: State<'_, AppState>,
+    handle: AppHandle,
+) -> Result<(), String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    let theme = state
+        .theme
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.is(&chat, id) {
+            c.toggle_star(messageIdx)?;
+            let app_data_dir = handle
+                .path()
+                .app_local_data_dir()
+                .map_err(|err| err.to_string())?;
+            let _ = save_basic_chat_data(&app_data_dir, &locked_chats, *theme);
+            return Ok(());
+        }
+    }
+    return Err("Failed to find chat".to_owned());
+}
+
+/// Gets the starred messages for the specified `chat`
+///
+/// Each message's `idx` is its current index, so it can be passed to `star_message` to unstar it.
+/// # Args
+/// * `chat` - Name of the chat
+/// * `id` - ID of the chat; if given, it's used instead of `chat`
+#[tauri::command]
+fn get_starred(
+    chat: String,
+    id: Option<Uuid>,
+    state: State<'_, AppState>,
+) -> Result<Vec<Message>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.is(&chat, id) {
+            return Ok(c.starred_messages());
+        }
+    }
+    return Err("Failed to find chat".to_owned());
+}
+
+/// Gets the revision of the specified chat, which increases whenever its messages (or which of them are starred)
+/// change, so the frontend can tell whether it needs to fetch them again
+/// # Args
+/// * `chat` - Name of the chat
+/// * `id` - ID of the chat; if given, it's used instead of `chat`
+#[tauri::command]
+fn get_chat_revision(
+    chat: String,
+    id: Option<Uuid>,
+    state: State<'_, AppState>,
+) -> Result<u64, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.is(&chat, id) {
+            return Ok(c.combined_revision());
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets the index of the nearest starred message before or after the given message
+///
+/// Returns `None` if there are no more starred messages in that direction.
+/// # Args
+/// * `chat` - Name of the chat
+/// * `id` - ID of the chat; if given, it's used instead of `chat`
+/// * `messageIdx` - Index of the current message
+/// * `forward` - Whether to look for the next starred message rather than the previous one
+#[tauri::command]
+#[allow(non_snake_case)]
+fn get_adjacent_starred(
+    chat: String,
+    id: Option<Uuid>,
+    messageIdx: usize,
+    forward: bool,
+    state: State<'_, AppState>,
+) -> Result<Option<usize>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.is(&chat, id) {
+            let is_starred = |i: &usize| c.messages[*i].starred.load(Relaxed);
+            return Ok(if forward {
+                (messageIdx.saturating_add(1)..c.messages.len()).find(is_starred)
+            } else {
+                (0..messageIdx.min(c.messages.len())).rev().find(is_starred)
+            });
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets the index of the nearest message before or after a given message or time, optionally from a given sender
+///
+/// Returns `None` if there are no more matching messages in that direction.
+/// # Args
+/// * `chat` - Name of the chat
+/// * `idx` - Index of the message to start from; takes precedence over `timestamp`
+/// * `timestamp` - Time to start from, if `idx` isn't given
+/// * `forward` - Whether to look for the next message rather than the previous one
+/// * `sender` - Only consider messages from this sender, if given; any "you" alias matches all of them
+#[tauri::command]
+fn neighbor_message(
+    chat: String,
+    idx: Option<usize>,
+    timestamp: Option<NaiveDateTime>,
+    forward: bool,
+    sender: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Option<usize>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let you = c.you.lock().map(|y| y.clone()).unwrap_or_default();
+            let senders = match sender {
+                Some(s) if you.contains(&s) => Some(you),
+                Some(s) => Some(vec![s]),
+                None => None,
+            };
+            let matches = |i: &usize| match &senders {
+                Some(senders) => c.messages[*i]
+                    .sender
+                    .as_ref()
+                    .is_some_and(|s| senders.contains(s)),
+                None => true,
+            };
+            // The range of messages strictly after (or before) the pivot
+            let (after, before) = match (idx, timestamp) {
+                (Some(idx), _) => (idx.saturating_add(1), idx.min(c.messages.len())),
+                (None, Some(timestamp)) => (
+                    c.messages.partition_point(|m| m.timestamp <= timestamp),
+                    c.messages.partition_point(|m| m.timestamp < timestamp),
+                ),
+                (None, None) => return Err("Either an index or a timestamp is required".to_owned()),
+            };
+            return Ok(if forward {
+                (after..c.messages.len()).find(matches)
+            } else {
+                (0..before).rev().find(matches)
+            });
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets chat statistics
+/// # Args
+/// * `chat` - Name of the chat
+/// * `exclude_you` - Whether to leave out the counts for "you"; defaults to `false`
+#[tauri::command]
+fn get_stats(
+    chat: String,
+    exclude_you: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<HashMap<String, MessageTypeCount>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let mut counts = c.count_by_sender();
+            if exclude_you.unwrap_or(false) {
+                let you = c.you.lock().map(|y| y.clone()).unwrap_or_default();
+                counts.retain(|sender, _| !you.contains(sender));
+            }
+            return Ok(counts);
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Compares two senders of the specified chat side by side
+///
+/// Errors if either sender hasn't sent any messages in the chat.
+/// # Args
+/// * `chat` - Name of the chat
+/// * `first` - First sender; defaults to "you"
+/// * `second` - Second sender
+#[tauri::command]
+fn compare_senders(
+    chat: String,
+    first: Option<String>,
+    second: String,
+    state: State<'_, AppState>,
+) -> Result<SenderComparison, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    let c = locked_chats
+        .iter()
+        .find(|c| c.name == chat)
+        .ok_or("Failed to find chat".to_owned())?;
+    let you = c.you.lock().map(|y| y.clone()).unwrap_or_default();
+    let canonical = |s: &String| {
+        if you.contains(s) {
+            you[0].clone()
+        } else {
+            s.clone()
+        }
+    };
+    let first = match first {
+        Some(first) => canonical(&first),
+        None => you
+            .first()
+            .cloned()
+            .ok_or("No first sender given and \"you\" isn't set".to_owned())?,
+    };
+    let second = canonical(&second);
+    if first == second {
+        return Err("Can't compare a sender with themselves".to_owned());
+    }
+    let mut counts = c.count_by_sender();
+    let mut metrics = Vec::with_capacity(2);
+    for sender in [first, second] {
+        let sender_counts = counts
+            .remove(&sender)
+            .ok_or(format!("{0} hasn't sent any messages in this chat", sender))?;
+        let mut total_length = 0;
+        let mut emoji = 0;
+        let mut questions = 0;
+        let mut first_of_day = 0;
+        let mut last_date = None;
+        for m in c.messages.iter() {
+            let is_sender = m.sender.as_ref().is_some_and(|s| canonical(s) == sender);
+            if last_date != Some(m.timestamp.date()) {
+                last_date = Some(m.timestamp.date());
+                if is_sender {
+                    first_of_day += 1;
+                }
+            }
+            if !is_sender {
+                continue;
+            }
+            let text = match &m.content {
+                MessageContent::Text(text) => {
+                    total_length += text.chars().count();
+                    text.as_str()
+                }
+                MessageContent::Media(media) => match &media.caption {
+                    Some(caption) => caption.as_str(),
+                    None => continue,
+                },
+                _ => continue,
+            };
+            emoji += text.chars().filter(|c| is_emoji(*c)).count() as u64;
+            if text.contains('?') {
+                questions += 1;
+            }
+        }
+        metrics.push(SenderMetrics {
+            average_length: if sender_counts.text == 0 {
+                None
+            } else {
+                Some(total_length as f64 / sender_counts.text as f64)
+            },
+            sender,
+            counts: sender_counts,
+            emoji,
+            questions,
+            first_of_day,
+        });
+    }
+    let second = metrics
+        .pop()
+        .ok_or("Failed to compare senders".to_owned())?;
+    let first = metrics
+        .pop()
+        .ok_or("Failed to compare senders".to_owned())?;
+    Ok(SenderComparison { first, second })
+}
+
+/// Gets the `n` most common items in `counts`, most common first (and alphabetically for ties)
+fn top_counts(counts: HashMap<String, u64>, n: usize) -> Vec<(String, u64)> {
+    let mut counts: Vec<(String, u64)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts.truncate(n);
+    counts
+}
+
+/// Escapes text for use in a Markdown table cell
+fn markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+/// Exports a Markdown report of the statistics of a chat
+///
+/// The report always includes the date span, totals, and a table of message counts per sender. The other sections
+/// require scanning every message's text, so are only included if requested.
+/// # Args
+/// * `chat` - Name of the chat
+/// * `path` - File to write the report to
+/// * `include_words` - Whether to include the most common words
+/// * `include_emoji` - Whether to include the most common emoji
+/// * `include_hours` - Whether to include the busiest hours of the day
+#[tauri::command]
+fn export_stats_markdown(
+    chat: String,
+    path: String,
+    include_words: Option<bool>,
+    include_emoji: Option<bool>,
+    include_hours: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    let c = locked_chats
+        .iter()
+        .find(|c| c.name == chat)
+        .ok_or("Failed to find chat".to_owned())?;
+    let mut report = format!("# {0}\n\n", c.name);
+    if let (Some(first), Some(last)) = (c.messages.first(), c.messages.last()) {
+        report.push_str(&format!(
+            "{0} to {1} ({2} days)\n\n",
+            first.timestamp.format("%Y-%m-%d"),
+            last.timestamp.format("%Y-%m-%d"),
+            (last.timestamp.date() - first.timestamp.date()).num_days() + 1
+        ));
+    }
+    let counts = c.count_by_sender();
+    let mut senders: Vec<(&String, &MessageTypeCount)> = counts.iter().collect();
+    let sender_total = |mtc: &MessageTypeCount| {
+        let media = &mtc.media;
+        mtc.text
+            + mtc.system
+            + mtc.deleted
+            + mtc.automated
+            + media.photo
+            + media.video
+            + media.audio
+            + media.voice_note
+            + media.other
+    };
+    senders.sort_by(|a, b| {
+        sender_total(b.1)
+            .cmp(&sender_total(a.1))
+            .then_with(|| a.0.cmp(b.0))
+    });
+    report.push_str(&format!("## Totals\n\n* Messages: {0}\n", c.messages.len()));
+    report.push_str(&format!("* Senders: {0}\n\n", senders.len()));
+    report.push_str("## Messages by sender\n\n");
+    report.push_str("| Sender | Text | Photos | Videos | Audio | Voice notes | Other files | Automated | Deleted | Total |\n");
+    report.push_str("| --- | ---: | ---: | ---: | ---: | ---: | ---: | ---: | ---: | ---: |\n");
+    for (sender, mtc) in senders {
+        report.push_str(&format!(
+            "| {0} | {1} | {2} | {3} | {4} | {5} | {6} | {7} | {8} | {9} |\n",
+            markdown_cell(sender),
+            mtc.text,
+            mtc.media.photo,
+            mtc.media.video,
+            mtc.media.audio,
+            mtc.media.voice_note,
+            mtc.media.other,
+            mtc.automated,
+            mtc.deleted,
+            sender_total(mtc)
+        ));
+    }
+    let texts = c.messages.iter().filter_map(|m| match &m.content {
+        MessageContent::Text(text) => Some(text.as_str()),
+        MessageContent::Media(media) => media.caption.as_deref(),
+        _ => None,
+    });
+    if include_words.unwrap_or(false) {
+        let mut words: HashMap<String, u64> = HashMap::new();
+        for text in texts.clone() {
+            for word in text
+                .to_lowercase()
+                .split(|c: char| !c.is_alphanumeric() && c != '\'')
+            {
+                // Short words are mostly articles and the like
+                if word.chars().count() > 3 {
+                    *words.entry(word.to_owned()).or_insert(0) += 1;
+                }
+            }
+        }
+        report.push_str("\n## Top words\n\n");
+        for (i, (word, count)) in top_counts(words, REPORT_TOP_COUNT).iter().enumerate() {
+            report.push_str(&format!("{0}. {1} ({2})\n", i + 1, word, count));
+        }
+    }
+    if include_emoji.unwrap_or(false) {
+        let mut emoji: HashMap<String, u64> = HashMap::new();
+        for text in texts {
+            for c in text.chars().filter(|c| is_emoji(*c)) {
+                *emoji.entry(c.to_string()).or_insert(0) += 1;
+            }
+        }
+        report.push_str("\n## Top emoji\n\n");
+        for (i, (e, count)) in top_counts(emoji, REPORT_TOP_COUNT).iter().enumerate() {
+            report.push_str(&format!("{0}. {1} ({2})\n", i + 1, e, count));
+        }
+    }
+    if include_hours.unwrap_or(false) {
+        let mut hours = [0u64; 24];
+        for m in c.messages.iter() {
+            hours[m.timestamp.hour() as usize] += 1;
+        }
+        let mut busiest: Vec<(usize, u64)> = hours.into_iter().enumerate().collect();
+        busiest.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        report.push_str("\n## Busiest hours\n\n");
+        for (i, (hour, count)) in busiest.iter().filter(|(_, n)| *n > 0).take(5).enumerate() {
+            report.push_str(&format!(
+                "{0}. {1:02}:00-{1:02}:59 ({2})\n",
+                i + 1,
+                hour,
+                count
+            ));
+        }
+    }
+    fs::write(&path, report).map_err(|e| e.to_string())
+}
+
+/// Gets who was in the specified (group) chat when, from the system messages about people joining and leaving
+///
+/// Someone whose first change is leaving is taken to have been in the group since before the export starts. Repeated
+/// changes (such as someone being added while they're already in the group) are ignored.
+/// # Args
+/// * `chat` - Name of the chat
+#[tauri::command]
+fn get_membership_timeline(
+    chat: String,
+    state: State<'_, AppState>,
+) -> Result<MembershipTimeline, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let you = c.you.lock().map(|y| y.clone()).unwrap_or_default();
+            let senders: HashSet<String> =
+                c.messages.iter().filter_map(|m| m.sender.clone()).collect();
+            let mut changes = Vec::new();
+            let mut periods: Vec<MembershipPeriod> = Vec::new();
+            // Index in `periods` of the current period of each person in the group
+            let mut current: HashMap<String, usize> = HashMap::new();
+            for m in c.messages.iter() {
+                let MessageContent::System(text) = &m.content else {
+                    continue;
+                };
+                let Some((event, members)) = membership_change(text, &senders) else {
+                    continue;
+                };
+                for member in members {
+                    let member = match you.first() {
+                        Some(first) if member == "You" || you.contains(&member) => first.clone(),
+                        _ => member,
+                    };
+                    let joining = matches!(event, SystemEvent::ADDED | SystemEvent::JOINED);
+                    match current.get(&member) {
+                        Some(_) if joining => continue,
+                        Some(&i) => {
+                            periods[i].left = Some(m.timestamp);
+                            current.remove(&member);
+                        }
+                        None if joining => {
+                            current.insert(member.clone(), periods.len());
+                            periods.push(MembershipPeriod {
+                                member: member.clone(),
+                                joined: Some(m.timestamp),
+                                left: None,
+                            });
+                        }
+                        None if periods.iter().any(|p| p.member == member) => continue,
+                        None => periods.push(MembershipPeriod {
+                            member: member.clone(),
+                            joined: None,
+                            left: Some(m.timestamp),
+                        }),
+                    }
+                    changes.push(MembershipChange {
+                        idx: m.idx,
+                        timestamp: m.timestamp,
+                        member,
+                        event,
+                    });
+                }
+            }
+            return Ok(MembershipTimeline { changes, periods });
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets the messages that start a new calendar day, for drawing date separators
+/// # Args
+/// * `chat` - Name of the chat
+#[tauri::command]
+fn get_day_boundaries(
+    chat: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<DayBoundary>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let mut boundaries = Vec::new();
+            let mut last_date = None;
+            for m in c.messages.iter() {
+                let date = m.timestamp.date();
+                if last_date != Some(date) {
+                    boundaries.push(DayBoundary { idx: m.idx, date });
+                    last_date = Some(date);
+                }
+            }
+            return Ok(boundaries);
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets the number of messages sent on every day of every year the chat spans, for drawing a heatmap
+///
+/// Days run from January 1st of the first message's year to December 31st of the last message's year, with
+/// days without messages included as zeros.
+/// # Args
+/// * `chat` - Name of the chat
+#[tauri::command]
+fn get_heatmap(chat: String, state: State<'_, AppState>) -> Result<Vec<DayCount>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let (first, last) = match (c.messages.first(), c.messages.last()) {
+                (Some(first), Some(last)) => (first.timestamp.date(), last.timestamp.date()),
+                _ => return Ok(Vec::new()),
+            };
+            let mut counts: HashMap<NaiveDate, u64> = HashMap::new();
+            for m in c.messages.iter() {
+                *counts.entry(m.timestamp.date()).or_insert(0) += 1;
+            }
+            let start = NaiveDate::from_ymd_opt(first.year(), 1, 1).unwrap_or(first);
+            let end = NaiveDate::from_ymd_opt(last.year(), 12, 31).unwrap_or(last);
+            return Ok(start
+                .iter_days()
+                .take_while(|d| *d <= end)
+                .map(|date| DayCount {
+                    date,
+                    count: counts.get(&date).copied().unwrap_or(0),
+                })
+                .collect());
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets the dates with at least one message in the specified chat, in order, such as for choosing a day to jump to
+/// # Args
+/// * `chat` - Name of the chat
+#[tauri::command]
+fn get_active_dates(chat: String, state: State<'_, AppState>) -> Result<Vec<NaiveDate>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let mut dates: Vec<NaiveDate> = c.messages.iter().map(|m| m.timestamp.date()).collect();
+            dates.sort_unstable();
+            dates.dedup();
+            return Ok(dates);
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets the longest streak of consecutive days with messages in the specified chat, and the streak that's ongoing as
+/// of the last message
+/// # Args
+/// * `chat` - Name of the chat
+#[tauri::command]
+fn get_streaks(chat: String, state: State<'_, AppState>) -> Result<Streaks, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let mut dates: Vec<NaiveDate> = c.messages.iter().map(|m| m.timestamp.date()).collect();
+            dates.sort_unstable();
+            dates.dedup();
+            let mut longest: Option<Streak> = None;
+            let mut current: Option<Streak> = None;
+            for date in dates {
+                let streak = match current {
+                    Some(streak) if streak.end.succ_opt() == Some(date) => Streak {
+                        end: date,
+                        days: streak.days + 1,
+                        ..streak
+                    },
+                    _ => Streak {
+                        start: date,
+                        end: date,
+                        days: 1,
+                    },
+                };
+                if longest.map_or(0, |l| l.days) < streak.days {
+                    longest = Some(streak);
+                }
+                current = Some(streak);
+            }
+            return Ok(Streaks { longest, current });
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Computes cadence stats from the number of messages sent on each active day
+/// # Parameters
+/// * `days` - Number of messages sent on each day with any messages
+/// * `first` - Date of the first message of the chat
+/// * `last` - Date of the last message of the chat
+fn cadence_stats(
+    days: &BTreeMap<NaiveDate, u64>,
+    first: NaiveDate,
+    last: NaiveDate,
+) -> CadenceStats {
+    let mut longest_active_streak = 0;
+    let mut longest_inactive_streak = 0;
+    let mut streak = 0;
+    let mut previous = first.pred_opt().unwrap_or(first);
+    for date in days.keys() {
+        let gap = (*date - previous).num_days() as u64 - 1;
+        streak = if gap == 0 { streak + 1 } else { 1 };
+        longest_active_streak = longest_active_streak.max(streak);
+        longest_inactive_streak = longest_inactive_streak.max(gap);
+        previous = *date;
+    }
+    longest_inactive_streak =
+        longest_inactive_streak.max((last - previous).num_days().max(0) as u64);
+    let total: u64 = days.values().sum();
+    CadenceStats {
+        days_spanned: (last - first).num_days() as u64 + 1,
+        active_days: days.len() as u64,
+        average_per_active_day: if days.is_empty() {
+            0.0
+        } else {
+            total as f64 / days.len() as f64
+        },
+        longest_active_streak,
+        longest_inactive_streak,
+    }
+}
+
+/// Gets how regularly messages were sent in the specified chat, overall and by each sender
+/// # Args
+/// * `chat` - Name of the chat
+#[tauri::command]
+fn get_cadence_stats(chat: String, state: State<'_, AppState>) -> Result<CadenceReport, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let (first, last) = match (c.messages.first(), c.messages.last()) {
+                (Some(first), Some(last)) => (first.timestamp.date(), last.timestamp.date()),
+                _ => return Err("The chat has no messages".to_owned()),
+            };
+            let you = c.you.lock().map(|y| y.clone()).unwrap_or_default();
+            let mut overall: BTreeMap<NaiveDate, u64> = BTreeMap::new();
+            let mut by_sender: HashMap<&String, BTreeMap<NaiveDate, u64>> = HashMap::new();
+            for m in c.messages.iter() {
+                let date = m.timestamp.date();
+                *overall.entry(date).or_insert(0) += 1;
+                if let Some(s) = &m.sender {
+                    let s = if you.contains(s) { &you[0] } else { s };
+                    *by_sender.entry(s).or_default().entry(date).or_insert(0) += 1;
+                }
+            }
+            return Ok(CadenceReport {
+                overall: cadence_stats(&overall, first, last),
+                by_sender: by_sender
+                    .into_iter()
+                    .map(|(s, days)| (s.clone(), cadence_stats(&days, first, last)))
+                    .collect(),
+            });
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets the days with the most messages, busiest first, along with where each day starts in the chat
+/// # Args
+/// * `chat` - Name of the chat
+/// * `n` - Number of days to return; defaults to 10
+#[tauri::command]
+fn get_busiest_day(
+    chat: String,
+    n: Option<usize>,
+    state: State<'_, AppState>,
+) -> Result<Vec<BusyDay>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            return Ok(c.busiest_days(n.unwrap_or(10)));
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets the average time of day each sender of the specified chat sends messages, and how consistently
+///
+/// Times of day are treated as angles around a clock, so that e.g. the average of 11 PM and 1 AM is midnight rather than
+/// noon. System messages are ignored.
+/// # Args
+/// * `chat` - Name of the chat
+#[tauri::command]
+fn get_active_hours_per_sender(
+    chat: String,
+    state: State<'_, AppState>,
+) -> Result<HashMap<String, ActiveHours>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let you = c.you.lock().map(|y| y.clone()).unwrap_or_default();
+            // Number of messages and sums of the unit vectors of their times of day
+            let mut sums: HashMap<String, (u64, f64, f64)> = HashMap::new();
+            for m in c.messages.iter() {
+                let sender = match (&m.sender, &m.content) {
+                    (_, MessageContent::System(_)) | (None, _) => continue,
+                    (Some(sender), _) if you.contains(sender) => &you[0],
+                    (Some(sender), _) => sender,
+                };
+                let angle = m.timestamp.num_seconds_from_midnight() as f64 / 86400.0
+                    * std::f64::consts::TAU;
+                let sum = sums.entry(sender.clone()).or_insert((0, 0.0, 0.0));
+                sum.0 += 1;
+                sum.1 += angle.cos();
+                sum.2 += angle.sin();
+            }
+            return Ok(sums
+                .into_iter()
+                .map(|(sender, (messages, cos, sin))| {
+                    let concentration = (cos * cos + sin * sin).sqrt() / messages as f64;
+                    let mean_hour = if concentration < 1e-9 {
+                        None
+                    } else {
+                        Some(
+                            sin.atan2(cos).rem_euclid(std::f64::consts::TAU)
+                                / std::f64::consts::TAU
+                                * 24.0,
+                        )
+                    };
+                    (
+                        sender,
+                        ActiveHours {
+                            messages,
+                            mean_hour,
+                            concentration,
+                        },
+                    )
+                })
+                .collect());
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets the number of messages sent in each hour of each day of the week
+///
+/// Returns a 7×24 grid indexed by weekday (starting from Monday) and then hour.
+/// # Args
+/// * `chat` - Name of the chat
+/// * `sender` - Only count messages from this sender, if given; any "you" alias counts all of them
+#[tauri::command]
+fn get_weekday_hour_grid(
+    chat: String,
+    sender: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<[[u64; 24]; 7], String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let you = c.you.lock().map(|y| y.clone()).unwrap_or_default();
+            let senders = match sender {
+                Some(s) if you.contains(&s) => Some(you),
+                Some(s) => Some(vec![s]),
+                None => None,
+            };
+            let mut grid = [[0; 24]; 7];
+            for m in c.messages.iter() {
+                if let Some(senders) = &senders {
+                    if !m.sender.as_ref().is_some_and(|s| senders.contains(s)) {
+                        continue;
+                    }
+                }
+                let weekday = m.timestamp.weekday().num_days_from_monday() as usize;
+                grid[weekday][m.timestamp.hour() as usize] += 1;
+            }
+            return Ok(grid);
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets the messages sent on the same month and day as `date` in prior years, grouped by year
+/// # Args
+/// * `chat` - Name of the chat
+/// * `date` - Date of interest; defaults to today
+/// * `fold_leap_day` - If `date` is February 29th, whether to use February 28th in years that aren't leap years
+#[tauri::command]
+fn on_this_day(
+    chat: String,
+    date: Option<NaiveDate>,
+    fold_leap_day: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<Vec<YearMessages>, String> {
+    let date = date.unwrap_or_else(|| Local::now().date_naive());
+    let fold_leap_day = fold_leap_day.unwrap_or(false) && date.month() == 2 && date.day() == 29;
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let mut years: Vec<YearMessages> = Vec::new();
+            for m in c.messages.iter() {
+                let d = m.timestamp.date();
+                if d.year() >= date.year() {
+                    break;
+                }
+                let matches = (d.month() == date.month() && d.day() == date.day())
+                    || (fold_leap_day && !d.leap_year() && d.month() == 2 && d.day() == 28);
+                if matches {
+                    // Messages are sorted, so the current year is always the last one
+                    match years.last_mut() {
+                        Some(y) if y.year == d.year() => y.messages.push(m.clone()),
+                        _ => years.push(YearMessages {
+                            year: d.year(),
+                            messages: vec![m.clone()],
+                        }),
+                    }
+                }
+            }
+            return Ok(years);
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets how often each sender sent runs of consecutive messages before someone else replied
+///
+/// For each sender, element `i` of the returned histogram is the number of runs of `i + 1` messages. System
+/// messages are ignored.
+/// # Args
+/// * `chat` - Name of the chat
+#[tauri::command]
+fn get_burst_stats(
+    chat: String,
+    state: State<'_, AppState>,
+) -> Result<HashMap<String, Vec<u64>>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let mut histograms: HashMap<String, Vec<u64>> = HashMap::new();
+            let mut record_run = |sender: &String, length: usize| {
+                let histogram = histograms.entry(sender.clone()).or_default();
+                if histogram.len() < length {
+                    histogram.resize(length, 0);
+                }
+                histogram[length - 1] += 1;
+            };
+            let mut run: Option<(&String, usize)> = None;
+            for m in c.messages.iter() {
+                let sender = match (&m.sender, &m.content) {
+                    (_, MessageContent::System(_)) | (None, _) => continue,
+                    (Some(sender), _) => sender,
+                };
+                run = match run {
+                    Some((run_sender, length)) if run_sender == sender => {
+                        Some((run_sender, length + 1))
+                    }
+                    Some((run_sender, length)) => {
+                        record_run(run_sender, length);
+                        Some((sender, 1))
+                    }
+                    None => Some((sender, 1)),
+                };
+            }
+            if let Some((run_sender, length)) = run {
+                record_run(run_sender, length);
+            }
+            return Ok(histograms);
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets how often each sender's messages continue their own previous message rather than following someone else's
+///
+/// Each message is attributed to the sender of the message immediately before it; system messages are ignored. All
+/// of the "you" aliases are counted under the first one.
+/// # Args
+/// * `chat` - Name of the chat
+#[tauri::command]
+fn get_reply_targets(
+    chat: String,
+    state: State<'_, AppState>,
+) -> Result<HashMap<String, ReplyTargets>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let you = c.you.lock().map(|y| y.clone()).unwrap_or_default();
+            let mut targets: HashMap<String, ReplyTargets> = HashMap::new();
+            let mut previous: Option<&String> = None;
+            for m in c.messages.iter() {
+                let sender = match (&m.sender, &m.content) {
+                    (_, MessageContent::System(_)) | (None, _) => continue,
+                    (Some(sender), _) if you.contains(sender) => &you[0],
+                    (Some(sender), _) => sender,
+                };
+                let sender_targets = targets.entry(sender.clone()).or_default();
+                match previous {
+                    Some(previous) if previous == sender => sender_targets.to_self += 1,
+                    Some(_) => sender_targets.to_other += 1,
+                    None => {}
+                }
+                previous = Some(sender);
+            }
+            for t in targets.values_mut() {
+                if t.to_self + t.to_other > 0 {
+                    t.self_fraction = t.to_self as f64 / (t.to_self + t.to_other) as f64;
+                }
+            }
+            return Ok(targets);
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets each sender's share of the messages and characters of the specified chat
+///
+/// System messages are ignored.
+/// # Args
+/// * `chat` - Name of the chat
+#[tauri::command]
+fn get_balance(chat: String, state: State<'_, AppState>) -> Result<Balance, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let you = c.you.lock().map(|y| y.clone()).unwrap_or_default();
+            let mut by_sender: HashMap<String, SenderBalance> = HashMap::new();
+            for m in c.messages.iter() {
+                let sender = match (&m.sender, &m.content) {
+                    (_, MessageContent::System(_)) | (None, _) => continue,
+                    (Some(sender), _) if you.contains(sender) => &you[0],
+                    (Some(sender), _) => sender,
+                };
+                let balance = by_sender.entry(sender.clone()).or_default();
+                balance.messages += 1;
+                if let Some(text) = searchable_text(&m.content) {
+                    balance.characters += text.chars().count() as u64;
+                }
+            }
+            let messages: u64 = by_sender.values().map(|b| b.messages).sum();
+            let characters: u64 = by_sender.values().map(|b| b.characters).sum();
+            for balance in by_sender.values_mut() {
+                if messages > 0 {
+                    balance.message_share = balance.messages as f64 / messages as f64;
+                }
+                if characters > 0 {
+                    balance.character_share = balance.characters as f64 / characters as f64;
+                }
+            }
+            let reciprocity = match by_sender.values().collect::<Vec<_>>()[..] {
+                [a, b] => Some(1.0 - (a.message_share - b.message_share).abs()),
+                _ => None,
+            };
+            return Ok(Balance {
+                messages,
+                characters,
+                reciprocity,
+                by_sender,
+            });
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets the distribution of the lengths of the text messages of the specified chat, overall and per sender
+///
+/// Lengths are counted in graphemes (so an emoji is a single character); empty messages are ignored.
+/// # Args
+/// * `chat` - Name of the chat
+/// * `edges` - Upper bounds (inclusive) of the buckets, in increasing order; defaults to `DEFAULT_LENGTH_BUCKET_EDGES`
+#[tauri::command]
+fn get_length_histogram(
+    chat: String,
+    edges: Option<Vec<usize>>,
+    state: State<'_, AppState>,
+) -> Result<LengthHistogram, String> {
+    let edges = edges.unwrap_or_else(|| DEFAULT_LENGTH_BUCKET_EDGES.to_vec());
+    if edges.first() == Some(&0) || edges.windows(2).any(|w| w[0] >= w[1]) {
+        return Err("Bucket edges must be positive and increasing".to_owned());
+    }
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let you = c.you.lock().map(|y| y.clone()).unwrap_or_default();
+            let mut overall = vec![0; edges.len() + 1];
+            let mut by_sender: HashMap<String, Vec<u64>> = HashMap::new();
+            for m in c.messages.iter() {
+                let (sender, text) = match (&m.sender, &m.content) {
+                    (Some(sender), MessageContent::Text(text)) if !text.is_empty() => {
+                        (sender, text)
+                    }
+                    _ => continue,
+                };
+                let sender = if you.contains(sender) {
+                    &you[0]
+                } else {
+                    sender
+                };
+                let length = text.graphemes(true).count();
+                let bucket = edges.partition_point(|&edge| edge < length);
+                overall[bucket] += 1;
+                by_sender
+                    .entry(sender.clone())
+                    .or_insert_with(|| vec![0; edges.len() + 1])[bucket] += 1;
+            }
+            return Ok(LengthHistogram {
+                edges,
+                overall,
+                by_sender,
+            });
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets the longest message of the specified chat, or `None` if it has no text messages
+///
+/// Lengths are counted in graphemes; if several messages are equally long, the first is returned.
+/// # Args
+/// * `chat` - Name of the chat
+/// * `include_captions` - Whether media captions count as messages; defaults to `false`
+#[tauri::command]
+fn get_longest_message(
+    chat: String,
+    include_captions: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<Option<LongestMessage>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    let include_captions = include_captions.unwrap_or(false);
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let mut longest: Option<(usize, &Message)> = None;
+            for m in c.messages.iter() {
+                let text = match &m.content {
+                    MessageContent::Text(text) => text,
+                    MessageContent::Media(Media {
+                        caption: Some(caption),
+                        ..
+                    }) if include_captions => caption,
+                    _ => continue,
+                };
+                let length = text.graphemes(true).count();
+                match longest {
+                    Some((longest_length, _)) if longest_length >= length => {}
+                    _ => longest = Some((length, m)),
+                }
+            }
+            return Ok(longest.map(|(length, m)| LongestMessage {
+                length,
+                message: m.clone(),
+            }));
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets how often each sender starts conversations rather than responding, and how quickly they reply
+///
+/// See `InitiativeBalance` for how conversations and replies are identified.
+/// # Args
+/// * `chat` - Name of the chat
+/// * `conversation_gap_minutes` - Gap between messages after which a new conversation starts; defaults to the chat's
+///   gap threshold (see `set_gap_threshold`)
+#[tauri::command]
+fn get_initiative_balance(
+    chat: String,
+    conversation_gap_minutes: Option<i64>,
+    state: State<'_, AppState>,
+) -> Result<InitiativeBalance, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let you = c.you.lock().map(|y| y.clone()).unwrap_or_default();
+            let gap_minutes = conversation_gap_minutes.unwrap_or_else(|| c.gap_threshold());
+            let gap = Duration::minutes(gap_minutes);
+            let mut by_sender: HashMap<String, SenderInitiative> = HashMap::new();
+            // Number of replies and their total time in minutes
+            let mut replies: HashMap<&String, (u64, i64)> = HashMap::new();
+            let mut conversations = 0;
+            // Senders in the current conversation, with its initiator first
+            let mut participants: Vec<&String> = Vec::new();
+            let mut record_conversation = |participants: &[&String]| {
+                for (i, p) in participants.iter().enumerate() {
+                    let stats = by_sender.entry((*p).clone()).or_default();
+                    if i == 0 {
+                        stats.initiated += 1;
+                    } else {
+                        stats.responded += 1;
+                    }
+                }
+            };
+            let mut previous: Option<(&String, NaiveDateTime)> = None;
+            for m in c.messages.iter() {
+                let sender = match (&m.sender, &m.content) {
+                    (_, MessageContent::System(_)) | (None, _) => continue,
+                    (Some(sender), _) if you.contains(sender) => &you[0],
+                    (Some(sender), _) => sender,
+                };
+                match previous {
+                    Some((_, timestamp)) if m.timestamp - timestamp > gap => {
+                        record_conversation(&participants);
+                        participants.clear();
+                        conversations += 1;
+                    }
+                    Some((previous_sender, timestamp)) if previous_sender != sender => {
+                        let (count, minutes) = replies.entry(sender).or_default();
+                        *count += 1;
+                        *minutes += (m.timestamp - timestamp).num_minutes();
+                    }
+                    _ => {}
+                }
+                if !participants.contains(&sender) {
+                    participants.push(sender);
+                }
+                previous = Some((sender, m.timestamp));
+            }
+            if !participants.is_empty() {
+                record_conversation(&participants);
+                conversations += 1;
+            }
+            for (sender, stats) in by_sender.iter_mut() {
+                stats.initiative_ratio = if stats.responded == 0 {
+                    None
+                } else {
+                    Some(stats.initiated as f64 / stats.responded as f64)
+                };
+                if let Some((count, minutes)) = replies.get(sender) {
+                    stats.replies = *count;
+                    stats.average_reply_minutes = Some(*minutes as f64 / *count as f64);
+                }
+            }
+            return Ok(InitiativeBalance {
+                conversation_gap_minutes: gap_minutes,
+                conversations,
+                by_sender,
+            });
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets the sender with the most messages in each month of a chat, in chronological order
+///
+/// Ties are broken alphabetically, with the other tied senders reported alongside the winner.
+/// # Args
+/// * `chat` - Name of the chat
+#[tauri::command]
+fn get_monthly_top_sender(
+    chat: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<MonthlyTopSender>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let you = c.you.lock().map(|y| y.clone()).unwrap_or_default();
+            let mut months: BTreeMap<(i32, u32), HashMap<&String, u64>> = BTreeMap::new();
+            for m in c.messages.iter() {
+                let Some(sender) = &m.sender else {
+                    continue;
+                };
+                let sender = if you.contains(sender) {
+                    &you[0]
+                } else {
+                    sender
+                };
+                let month = (m.timestamp.year(), m.timestamp.month());
+                *months.entry(month).or_default().entry(sender).or_insert(0) += 1;
+            }
+            return Ok(months
+                .into_iter()
+                .map(|((year, month), counts)| {
+                    let count = counts.values().copied().max().unwrap_or(0);
+                    let mut top: Vec<&String> = counts
+                        .into_iter()
+                        .filter(|(_, n)| *n == count)
+                        .map(|(s, _)| s)
+                        .collect();
+                    top.sort();
+                    MonthlyTopSender {
+                        year,
+                        month,
+                        sender: top[0].clone(),
+                        count,
+                        tied_with: top[1..].iter().map(|s| (*s).clone()).collect(),
+                    }
+                })
+                .collect());
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets the proportion of media to text messages in the specified chat over time
+///
+/// Every period from the first message to the last is included, even ones without any messages.
+/// # Args
+/// * `chat` - Name of the chat
+/// * `granularity` - Length of each period
+/// * `sender` - Only count messages from this sender, if provided; any of the "you" aliases matches all of them
+#[tauri::command]
+fn get_media_ratio_timeline(
+    chat: String,
+    granularity: Granularity,
+    sender: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Vec<MediaRatio>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let you = c.you.lock().map(|y| y.clone()).unwrap_or_default();
+            let sender_matches = |s: &Option<String>| match (&sender, s) {
+                (None, _) => true,
+                (Some(wanted), Some(s)) => s == wanted || (you.contains(wanted) && you.contains(s)),
+                (Some(_), None) => false,
+            };
+            let mut counts: HashMap<NaiveDate, (u64, u64)> = HashMap::new();
+            for m in c.messages.iter().filter(|m| sender_matches(&m.sender)) {
+                let count = counts
+                    .entry(granularity.period_start(m.timestamp.date()))
+                    .or_default();
+                match &m.content {
+                    MessageContent::Media(_) => count.0 += 1,
+                    MessageContent::Text(_) if !m.automated => count.1 += 1,
+                    _ => {}
+                }
+            }
+            let (Some(first), Some(last)) = (c.messages.first(), c.messages.last()) else {
+                return Ok(Vec::new());
+            };
+            return Ok(granularity
+                .periods(first.timestamp.date(), last.timestamp.date())
+                .into_iter()
+                .map(|start| {
+                    let (media, text) = counts.get(&start).copied().unwrap_or_default();
+                    MediaRatio {
+                        start,
+                        media,
+                        text,
+                        media_fraction: if media + text == 0 {
+                            None
+                        } else {
+                            Some(media as f64 / (media + text) as f64)
+                        },
+                    }
+                })
+                .collect());
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets the amount of text written in the specified chat over time, overall and by each sender
+///
+/// Text messages and media captions are counted. Every period from the first message to the last is included, even
+/// ones without any messages. All of the "you" aliases are counted under the first one.
+/// # Args
+/// * `chat` - Name of the chat
+/// * `granularity` - Length of each period
+#[tauri::command]
+fn get_volume_timeline(
+    chat: String,
+    granularity: Granularity,
+    state: State<'_, AppState>,
+) -> Result<Vec<VolumePeriod>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let you = c.you.lock().map(|y| y.clone()).unwrap_or_default();
+            let mut periods: HashMap<NaiveDate, HashMap<&String, TextVolume>> = HashMap::new();
+            for m in c.messages.iter() {
+                let (Some(sender), Some(text)) = (&m.sender, searchable_text(&m.content)) else {
+                    continue;
+                };
+                if matches!(m.content, MessageContent::System(_)) {
+                    continue;
+                }
+                let sender = if you.contains(sender) {
+                    &you[0]
+                } else {
+                    sender
+                };
+                let volume = periods
+                    .entry(granularity.period_start(m.timestamp.date()))
+                    .or_default()
+                    .entry(sender)
+                    .or_default();
+                volume.words += text.split_whitespace().count() as u64;
+                volume.characters += text.chars().count() as u64;
+            }
+            let (Some(first), Some(last)) = (c.messages.first(), c.messages.last()) else {
+                return Ok(Vec::new());
+            };
+            return Ok(granularity
+                .periods(first.timestamp.date(), last.timestamp.date())
+                .into_iter()
+                .map(|start| {
+                    let mut total = TextVolume::default();
+                    let mut by_sender = HashMap::new();
+                    for (sender, volume) in periods.remove(&start).unwrap_or_default() {
+                        total.words += volume.words;
+                        total.characters += volume.characters;
+                        by_sender.insert(sender.clone(), volume);
+                    }
+                    VolumePeriod {
+                        start,
+                        total,
+                        by_sender,
+                    }
+                })
+                .collect());
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Finds groups of repeated messages (such as double-sends or forwarded spam)
+/// # Args
+/// * `chat` - Name of the chat
+/// * `strict` - Whether duplicates must also have identical timestamps, rather than being within 12 hours of each
+///   other; defaults to `false`
+#[tauri::command]
+fn find_duplicates(
+    chat: String,
+    strict: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<Vec<Vec<usize>>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    let strict = strict.unwrap_or(false);
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let mut grouped = vec![false; c.messages.len()];
+            let mut groups = Vec::new();
+            for i in 0..c.messages.len() {
+                if grouped[i] {
+                    continue;
+                }
+                let m = &c.messages[i];
+                let mut group = vec![m.idx];
+                // Messages are sorted, so only those within the 12 hour window need to be checked
+                for (j, other) in c.messages.iter().enumerate().skip(i + 1) {
+                    if other.timestamp > m.timestamp + Duration::hours(12)
+                        || (strict && other.timestamp != m.timestamp)
+                    {
+                        break;
+                    }
+                    if !grouped[j] && m == other {
+                        grouped[j] = true;
+                        group.push(other.idx);
+                    }
+                }
+                if group.len() > 1 {
+                    groups.push(group);
+                }
+            }
+            return Ok(groups);
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets the messages at which the cumulative message count reached round numbers
+/// # Args
+/// * `chat` - Name of the chat
+/// * `milestones` - Counts of interest; if not provided, powers of ten and multiples of 10,000 are used
+#[tauri::command]
+fn get_milestones(
+    chat: String,
+    milestones: Option<Vec<usize>>,
+    state: State<'_, AppState>,
+) -> Result<Vec<Milestone>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let total = c.messages.len();
+            let mut counts = match milestones {
+                Some(m) => m,
+                None => {
+                    let mut defaults: Vec<usize> = (0..)
+                        .map(|p| 10_usize.pow(p))
+                        .take_while(|&n| n <= 10_000.min(total))
+                        .collect();
+                    defaults.extend((20_000..=total).step_by(10_000));
+                    defaults
+                }
+            };
+            counts.sort_unstable();
+            counts.dedup();
+            return Ok(counts
+                .into_iter()
+                .filter(|&n| n >= 1 && n <= total)
+                .map(|n| Milestone {
+                    count: n,
+                    message: c.messages[n - 1].clone(),
+                })
+                .collect());
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets whether the specified chat is a direct message or a group, along with its participants
+///
+/// Only the senders of actual messages are counted, since system messages may be attributed to other names.
+/// All the "you" aliases count as a single participant.
+/// # Args
+/// * `chat` - Name of the chat
+#[tauri::command]
+fn get_chat_type(chat: String, state: State<'_, AppState>) -> Result<ChatType, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let you = c.you.lock().map(|y| y.clone()).unwrap_or_default();
+            let mut participants: HashSet<&String> = HashSet::new();
+            for m in c.messages.iter() {
+                match (&m.sender, &m.content) {
+                    (_, MessageContent::System(_)) | (None, _) => continue,
+                    (Some(sender), _) => {
+                        participants.insert(if you.contains(sender) {
+                            &you[0]
+                        } else {
+                            sender
+                        });
+                    }
+                }
+            }
+            let mut participants: Vec<String> = participants.into_iter().cloned().collect();
+            participants.sort();
+            return Ok(ChatType {
+                kind: if participants.len() > 2 {
+                    ChatKind::GROUP
+                } else {
+                    ChatKind::DIRECT
+                },
+                participant_count: participants.len(),
+                participants,
+            });
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Extracts the URLs from `text`
+///
+/// URLs start with `http://`, `https://`, or `www.`, and end at whitespace. Surrounding punctuation (such as a
+/// full stop ending the sentence, or parentheses around the URL) isn't included.
+fn extract_urls(text: &str) -> Vec<&str> {
+    let mut urls = Vec::new();
+    for word in text.split_whitespace() {
+        let lower = word.to_ascii_lowercase();
+        let Some(start) = ["http://", "https://", "www."]
+            .iter()
+            .filter_map(|prefix| lower.find(prefix))
+            .min()
+        else {
+            continue;
+        };
+        let mut url = &word[start..];
+        loop {
+            let trimmed =
+                url.trim_end_matches(['.', ',', ';', ':', '!', '?', '\'', '"', '>', ']', '}']);
+            // Only strip a closing parenthesis that isn't part of the URL itself (as in Wikipedia links)
+            url = match trimmed.strip_suffix(')') {
+                Some(t) if trimmed.matches('(').count() < trimmed.matches(')').count() => t,
+                _ => trimmed,
+            };
+            if url == trimmed {
+                break;
+            }
+        }
+        if url.contains('.') {
+            urls.push(url);
+        }
+    }
+    urls
+}
+
+/// Masks the middle digits of a sender that's a phone number (such as `+1 234 567 8990` to `+1 234 ••• ••90`), for
+/// showing the chat without revealing the numbers
+///
+/// The first four and last two digits are kept so that participants can still be told apart. Senders that aren't
+/// phone numbers are returned unchanged.
+fn redact_phone_number(sender: &str) -> String {
+    // Numbers are often exported wrapped in directional formatting characters
+    let number =
+        sender.trim_matches(|c: char| c.is_whitespace() || matches!(c, '\u{202a}' | '\u{202c}'));
+    let is_number = match &extract_phone_numbers(number)[..] {
+        [(_, false, range)] => *range == (0..number.len()),
+        _ => false,
+    };
+    if !is_number {
+        return sender.to_owned();
+    }
+    let digits = sender.chars().filter(char::is_ascii_digit).count();
+    let mut seen = 0;
+    sender
+        .chars()
+        .map(|c| {
+            if !c.is_ascii_digit() {
+                return c;
+            }
+            seen += 1;
+            if seen <= 4 || seen > digits - 2 {
+                c
+            } else {
+                '•'
+            }
+        })
+        .collect()
+}
+
+/// Extracts the @-mentions and phone numbers from `text`
+///
+/// Returns the digits of each number along with whether it was @-mentioned and its (byte) position in `text`. Group
+/// chat mentions are exported as the raw number (e.g. `@15551234567`). Other numbers are only included if they
+/// start with `+` and have at least 7 digits, or have at least 10 digits, so that dates and other numbers aren't
+/// mistaken for phone numbers.
+fn extract_phone_numbers(text: &str) -> Vec<(String, bool, Range<usize>)> {
+    let chars: Vec<char> = text.chars().collect();
+    let offsets: Vec<usize> = text
+        .char_indices()
+        .map(|(o, _)| o)
+        .chain([text.len()])
+        .collect();
+    let mut numbers = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let at_word_start = i == 0 || !chars[i - 1].is_alphanumeric();
+        if c == '@' && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()) {
+            let digits: String = chars[i + 1..]
+                .iter()
+                .take_while(|c| c.is_ascii_digit())
+                .collect();
+            let end = i + 1 + digits.len();
+            numbers.push((digits, true, offsets[i]..offsets[end]));
+            i = end;
+        } else if at_word_start && (c == '+' || c.is_ascii_digit()) {
+            let run: Vec<char> = chars[i..]
+                .iter()
+                .copied()
+                .take_while(|c| {
+                    c.is_ascii_digit() || matches!(c, ' ' | '-' | '(' | ')' | '.' | '+')
+                })
+                .collect();
+            let digits: String = run.iter().filter(|c| c.is_ascii_digit()).collect();
+            let min_digits = if c == '+' { 7 } else { 10 };
+            // Trailing separators (such as the space before the next word) aren't part of the number
+            let end = run
+                .iter()
+                .rposition(|c| c.is_ascii_digit())
+                .map_or(0, |p| p + 1);
+            let at_word_end = !chars.get(i + end).is_some_and(|c| c.is_alphanumeric());
+            if at_word_end && (min_digits..=15).contains(&digits.len()) {
+                numbers.push((digits, false, offsets[i]..offsets[i + end]));
+            }
+            i += run.len().max(1);
+        } else {
+            i += 1;
+        }
+    }
+    numbers
+}
+
+/// Gets the phone numbers each sender @-mentioned or wrote out in the specified chat, most common first
+///
+/// Numbers are resolved to the sender with the same number as their name, if there is one.
+/// # Args
+/// * `chat` - Name of the chat
+#[tauri::command]
+fn get_mentions(
+    chat: String,
+    state: State<'_, AppState>,
+) -> Result<HashMap<String, Vec<MentionCount>>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let you = c.you.lock().map(|y| y.clone()).unwrap_or_default();
+            // Senders whose names are phone numbers (i.e. contacts that weren't saved), by their digits
+            let mut names: HashMap<String, &String> = HashMap::new();
+            for s in c.messages.iter().filter_map(|m| m.sender.as_ref()) {
+                if s.chars()
+                    .all(|c| c.is_ascii_digit() || matches!(c, ' ' | '-' | '(' | ')' | '.' | '+'))
+                {
+                    names.insert(s.chars().filter(|c| c.is_ascii_digit()).collect(), s);
+                }
+            }
+            let mut by_sender: HashMap<String, HashMap<String, (u64, u64)>> = HashMap::new();
+            for m in c.messages.iter() {
+                let text = match &m.content {
+                    MessageContent::Text(text) => text.as_str(),
+                    MessageContent::Media(media) => match &media.caption {
+                        Some(caption) => caption.as_str(),
+                        None => continue,
+                    },
+                    _ => continue,
+                };
+                let Some(sender) = &m.sender else {
+                    continue;
+                };
+                let sender = if you.contains(sender) {
+                    &you[0]
+                } else {
+                    sender
+                };
+                for (number, mention, _) in extract_phone_numbers(text) {
+                    let counts = by_sender
+                        .entry(sender.clone())
+                        .or_default()
+                        .entry(number)
+                        .or_insert((0, 0));
+                    if mention {
+                        counts.0 += 1;
+                    } else {
+                        counts.1 += 1;
+                    }
+                }
+            }
+            return Ok(by_sender
+                .into_iter()
+                .map(|(sender, numbers)| {
+                    let mut counts: Vec<MentionCount> = numbers
+                        .into_iter()
+                        .map(|(number, (mentions, written))| MentionCount {
+                            name: names.get(&number).map(|s| (*s).clone()),
+                            number,
+                            mentions,
+                            written,
+                        })
+                        .collect();
+                    counts.sort_by(|a, b| {
+                        (b.mentions + b.written)
+                            .cmp(&(a.mentions + a.written))
+                            .then_with(|| a.number.cmp(&b.number))
+                    });
+                    (sender, counts)
+                })
+                .collect());
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets every URL shared in the specified chat's messages and media captions, in chronological order
+/// # Args
+/// * `chat` - Name of the chat
+/// * `deduplicate` - Whether to only include the first time each URL was shared; defaults to `false`
+#[tauri::command]
+fn get_links(
+    chat: String,
+    deduplicate: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<Vec<SharedLink>, String> {
+    let deduplicate = deduplicate.unwrap_or(false);
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let mut seen: HashSet<&str> = HashSet::new();
+            let mut links = Vec::new();
+            for m in c.messages.iter() {
+                let text = match &m.content {
+                    MessageContent::Text(text) => text.as_str(),
+                    MessageContent::Media(media) => match &media.caption {
+                        Some(caption) => caption.as_str(),
+                        None => continue,
+                    },
+                    _ => continue,
+                };
+                for url in extract_urls(text) {
+                    if deduplicate && !seen.insert(url) {
+                        continue;
+                    }
+                    links.push(SharedLink {
+                        url: url.to_owned(),
+                        sender: m.sender.clone(),
+                        timestamp: m.timestamp,
+                        idx: m.idx,
+                    });
+                }
+            }
+            return Ok(links);
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets the media messages of the specified chat grouped by sender, along with a count of each type
+///
+/// Senders who haven't sent any media aren't included.
+/// # Args
+/// * `chat` - Name of the chat
+#[tauri::command]
+fn get_media_by_sender(chat: String, state: State<'_, AppState>) -> Result<GalleryMedia, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let mut to_return: HashMap<String, Vec<MediaSummary>> = HashMap::new();
+            let mut counts: HashMap<String, GalleryMediaCount> = HashMap::new();
+            for m in c.messages.iter() {
+                if let (Some(sender), MessageContent::Media(media)) = (&m.sender, &m.content) {
+                    let count = counts.entry(sender.clone()).or_default();
+                    match media.media_type {
+                        _ if media.path.is_none() => count.unresolved += 1,
+                        _ if media.voice_note => count.resolved.voice_note += 1,
+                        MediaType::PHOTO => count.resolved.photo += 1,
+                        MediaType::VIDEO => count.resolved.video += 1,
+                        MediaType::AUDIO => count.resolved.audio += 1,
+                        MediaType::OTHER => count.resolved.other += 1,
+                    }
+                    to_return
+                        .entry(sender.clone())
+                        .or_default()
+                        .push(MediaSummary {
+                            media_type: media.media_type,
+                            voice_note: media.voice_note,
+                            path: media.path.clone(),
+                            caption: media.caption.clone(),
+                            timestamp: m.timestamp,
+                            idx: m.idx,
+                        });
+                }
+            }
+            return Ok(GalleryMedia {
+                media: to_return,
+                counts,
+            });
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Finds the media messages in all of the loaded chats that reference the given file
+///
+/// Matching is on the file's name (ignoring any directories) and case-insensitive, and includes media that wasn't
+/// found in the media directory. Returns the name of the chat and the index of the message for each reference.
+/// # Args
+/// * `file_name` - Name of (or path to) the file
+#[tauri::command]
+fn find_media_references(
+    file_name: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<(String, usize)>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    let base_name = |name: &str| {
+        Path::new(name)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_lowercase())
+    };
+    let Some(wanted) = base_name(&file_name) else {
+        return Ok(Vec::new());
+    };
+    let mut references = Vec::new();
+    for c in locked_chats.iter() {
+        for m in c.messages.iter() {
+            if let MessageContent::Media(Media {
+                file_name: Some(referenced),
+                ..
+            }) = &m.content
+            {
+                if base_name(referenced).as_ref() == Some(&wanted) {
+                    references.push((c.name.clone(), m.idx));
+                }
+            }
+        }
+    }
+    Ok(references)
+}
+
+/// Estimates whether the specified chat was exported without its media, in which case looking for media files is
+/// pointless
+/// # Args
+/// * `chat` - Name of the chat
+#[tauri::command]
+fn media_stripped_heuristic(
+    chat: String,
+    state: State<'_, AppState>,
+) -> Result<MediaStrippedEstimate, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let (resolved, unresolved, omitted) = c.count_media_resolution();
+            let total = resolved + unresolved + omitted;
+            let omitted_fraction = if total == 0 {
+                None
+            } else {
+                Some(omitted as f64 / total as f64)
+            };
+            return Ok(MediaStrippedEstimate {
+                resolved,
+                unresolved,
+                omitted,
+                omitted_fraction,
+                stripped: omitted_fraction.is_some_and(|f| f >= MEDIA_STRIPPED_FRACTION),
+            });
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Copies the media of the specified chat into `directory`, in the order it was sent, along with a manifest
+///
+/// Each file is renamed to its (1-based) position followed by its original name, e.g. `0001_IMG-20220101-WA0001.jpg`.
+/// The manifest (`MEDIA_MANIFEST_NAME`) lists when, by whom, and with what caption each file was sent. Media whose
+/// file wasn't found is skipped.
+/// # Args
+/// * `chat` - Name of the chat
+/// * `directory` - Directory to copy the media into; it's created if it doesn't exist
+#[tauri::command]
+fn export_media_bundle(
+    chat: String,
+    directory: String,
+    state: State<'_, AppState>,
+) -> Result<MediaBundleResult, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    let c = locked_chats
+        .iter()
+        .find(|c| c.name == chat)
+        .ok_or("Failed to find chat".to_owned())?;
+    let mut to_copy = Vec::new();
+    let mut skipped = Vec::new();
+    for m in c.messages.iter() {
+        if let MessageContent::Media(media) = &m.content {
+            match &media.path {
+                Some(path) => to_copy.push((m, media, path)),
+                None => skipped.push(m.idx),
+            }
+        }
+    }
+    let directory = Path::new(&directory);
+    create_dir_all(directory).map_err(|e| e.to_string())?;
+    let width = to_copy.len().to_string().len().max(4);
+    let mut manifest = Vec::with_capacity(to_copy.len());
+    for (i, (m, media, path)) in to_copy.into_iter().enumerate() {
+        let original = match &media.file_name {
+            Some(file_name) => file_name.clone(),
+            None => Path::new(path)
+                .file_name()
+                .map(|f| f.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        };
+        let file = format!("{0:01$}_{2}", i + 1, width, original);
+        fs::copy(path, directory.join(&file))
+            .map_err(|e| format!("Failed to copy {0}: {1}", path, e))?;
+        manifest.push(BundledMedia {
+            file,
+            original,
+            timestamp: m.timestamp,
+            sender: m.sender.clone(),
+            caption: media.caption.clone(),
+            idx: m.idx,
+        });
+    }
+    let data = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+    fs::write(directory.join(MEDIA_MANIFEST_NAME), data).map_err(|e| e.to_string())?;
+    Ok(MediaBundleResult {
+        copied: manifest.len(),
+        skipped,
+    })
+}
+
+/// Detects the languages the specified chat is written in, from a sample of its text messages
+///
+/// Returns the detected languages, most common first.
+/// # Args
+/// * `chat` - Name of the chat
+#[tauri::command]
+fn detect_language(
+    chat: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<LanguageProportion>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    let c = locked_chats
+        .iter()
+        .find(|c| c.name == chat)
+        .ok_or("Failed to find chat".to_owned())?;
+    let texts: Vec<&String> = c
+        .messages
+        .iter()
+        .filter_map(|m| match &m.content {
+            MessageContent::Text(text) => Some(text),
+            _ => None,
+        })
+        .collect();
+    // Sample evenly across the chat so that both old and new messages are considered
+    let step = (texts.len() / LANGUAGE_SAMPLE_SIZE).max(1);
+    let mut detected: HashMap<whatlang::Lang, (usize, f64)> = HashMap::new();
+    let mut total = 0;
+    for text in texts.iter().step_by(step).take(LANGUAGE_SAMPLE_SIZE) {
+        if let Some(info) = whatlang::detect(text) {
+            let entry = detected.entry(info.lang()).or_insert((0, 0.0));
+            entry.0 += 1;
+            entry.1 += info.confidence();
+            total += 1;
+        }
+    }
+    let mut proportions: Vec<LanguageProportion> = detected
+        .into_iter()
+        .map(|(lang, (count, confidence))| LanguageProportion {
+            code: lang.code(),
+            name: lang.eng_name(),
+            proportion: count as f64 / total as f64,
+            confidence: confidence / count as f64,
+        })
+        .collect();
+    proportions.sort_by(|a, b| b.proportion.total_cmp(&a.proportion));
+    Ok(proportions)
+}
+
+/// Counts the positive and negative lexicon entries in `text`
+///
+/// Words are matched case-insensitively as whole words, and emoji are matched regardless of skin tone and variation.
+/// # Parameters
+/// * `text` - Text to score
+/// * `positive` - Lowercased positive words and emoji
+/// * `negative` - Lowercased negative words and emoji
+fn score_text(text: &str, positive: &HashSet<String>, negative: &HashSet<String>) -> (u64, u64) {
+    let lower = strip_emoji_modifiers(&text.to_lowercase());
+    let mut pos = 0;
+    let mut neg = 0;
+    let mut count = |token: &str| {
+        if positive.contains(token) {
+            pos += 1;
+        } else if negative.contains(token) {
+            neg += 1;
+        }
+    };
+    for word in lower.split(|c: char| !c.is_alphanumeric() && c != '\'') {
+        if !word.is_empty() {
+            count(word);
+        }
+    }
+    // Emoji aren't part of words, so are checked individually
+    for c in lower.chars() {
+        if !c.is_alphanumeric() && !c.is_whitespace() && !c.is_ascii() {
+            count(c.encode_utf8(&mut [0; 4]));
+        }
+    }
+    (pos, neg)
+}
+
+/// Estimates the "mood" of a chat overall, for each sender, and over time
+///
+/// This is purely a heuristic: it counts words and emoji from a small lexicon of positive and negative terms
+/// in message text and captions, without any understanding of context, negation, or sarcasm. The built-in
+/// lexicon is English; other lexicons can be supplied instead.
+/// # Args
+/// * `chat` - Name of the chat
+/// * `positive` - Words and emoji to count as positive, replacing the built-in list
+/// * `negative` - Words and emoji to count as negative, replacing the built-in list
+#[tauri::command]
+fn get_sentiment_estimate(
+    chat: String,
+    positive: Option<Vec<String>>,
+    negative: Option<Vec<String>>,
+    state: State<'_, AppState>,
+) -> Result<SentimentEstimate, String> {
+    let to_lexicon = |words: Option<Vec<String>>, default: &[&str]| -> HashSet<String> {
+        match words {
+            Some(words) => words
+                .iter()
+                .map(|w| strip_emoji_modifiers(&w.trim().to_lowercase()))
+                .collect(),
+            None => default.iter().map(|w| w.to_string()).collect(),
+        }
+    };
+    let positive = to_lexicon(positive, &POSITIVE_LEXICON);
+    let negative = to_lexicon(negative, &NEGATIVE_LEXICON);
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    let c = locked_chats
+        .iter()
+        .find(|c| c.name == chat)
+        .ok_or("Failed to find chat".to_owned())?;
+    let you = c.you.lock().map(|y| y.clone()).unwrap_or_default();
+    let mut overall = SentimentScore::default();
+    let mut by_sender: HashMap<String, SentimentScore> = HashMap::new();
+    let mut by_month: BTreeMap<(i32, u32), SentimentScore> = BTreeMap::new();
+    for m in c.messages.iter() {
+        let text = match &m.content {
+            MessageContent::Text(text) => Some(text.as_str()),
+            MessageContent::Media(media) => media.caption.as_deref(),
+            _ => None,
+        };
+        let (Some(sender), Some(text)) = (&m.sender, text) else {
+            continue;
+        };
+        let (pos, neg) = score_text(text, &positive, &negative);
+        if pos + neg == 0 {
+            continue;
+        }
+        let sender = if you.contains(sender) {
+            &you[0]
+        } else {
+            sender
+        };
+        overall.add(pos, neg);
+        by_sender.entry(sender.clone()).or_default().add(pos, neg);
+        by_month
+            .entry((m.timestamp.year(), m.timestamp.month()))
+            .or_default()
+            .add(pos, neg);
+    }
+    Ok(SentimentEstimate {
+        overall,
+        by_sender,
+        by_month: by_month
+            .into_iter()
+            .map(|((year, month), score)| MonthSentiment { year, month, score })
+            .collect(),
+    })
+}
+
+/// Finds the Nth message matching the given filters, e.g. "the first photo Alice sent"
+///
+/// Returns `None` if there are fewer than `n` matching messages.
+/// # Args
+/// * `chat` - Name of the chat
+/// * `sender` - Sender of the message, if any
+/// * `kind` - Kind of message content, if any
+/// * `n` - Ordinal of the message to find, starting from 1
+/// * `from_end` - Whether to count from the most recent message instead of the first; defaults to `false`
+#[tauri::command]
+fn find_nth_by(
+    chat: String,
+    sender: Option<String>,
+    kind: Option<ContentKind>,
+    n: usize,
+    from_end: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<Option<usize>, String> {
+    if n == 0 {
+        return Err("Ordinal must be at least 1".to_owned());
+    }
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            let is_match = |m: &&Message| {
+                let kind_matches = match kind {
+                    Some(k) => k.matches(&m.content),
+                    None => true,
+                };
+                kind_matches && (sender.is_none() || m.sender == sender)
+            };
+            let found = if from_end.unwrap_or(false) {
+                c.messages.iter().rev().filter(is_match).nth(n - 1)
+            } else {
+                c.messages.iter().filter(is_match).nth(n - 1)
+            };
+            return Ok(found.map(|m| m.idx));
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Computes a stable anchor for each message from its timestamp, sender, and content
+///
+/// Anchors are a 64-bit FNV-1a hash, so they don't change between runs or when the chat is re-exported.
+/// Identical messages are disambiguated by appending the number of previous occurrences.
+fn message_anchors(messages: &[Message]) -> Vec<String> {
+    let mut seen: HashMap<u64, usize> = HashMap::new();
+    messages
+        .iter()
+        .map(|m| {
+            // Media is identified by its file name so that changing the media directory doesn't change the anchor
+            let content = match &m.content {
+                MessageContent::Media(media) => format!(
+                    "{0}\u{0}{1}",
+                    media.file_name.as_deref().unwrap_or_default(),
+                    media.caption.as_deref().unwrap_or_default()
+                ),
+                other => content_to_string(other, false),
+            };
+            let key = format!(
+                "{0}\u{0}{1}\u{0}{2}",
+                m.timestamp.format(EXPORT_TIME_FORMAT),
+                m.sender.as_deref().unwrap_or_default(),
+                content
+            );
+            let hash = key.bytes().fold(0xcbf29ce484222325_u64, |h, b| {
+                (h ^ u64::from(b)).wrapping_mul(0x100000001b3)
+            });
+            let occurrence = seen.entry(hash).or_insert(0);
+            let anchor = if *occurrence == 0 {
+                format!("{0:016x}", hash)
+            } else {
+                format!("{0:016x}-{1}", hash, occurrence)
+            };
+            *occurrence += 1;
+            anchor
+        })
+        .collect()
+}
+
+/// Gets the index of the message with the given anchor, if it still exists
+/// # Args
+/// * `chat` - Name of the chat
+/// * `anchor` - Anchor of the message (see `message_anchors`)
+#[tauri::command]
+fn resolve_anchor(
+    chat: String,
+    anchor: String,
+    state: State<'_, AppState>,
+) -> Result<Option<usize>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in locked_chats.iter() {
+        if c.name == chat {
+            return Ok(c
+                .anchors
+                .iter()
+                .position(|a| *a == anchor)
+                .map(|i| c.messages[i].idx));
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets the lowercased extension of a file name, if it has one
+fn file_extension(file_name: &str) -> Option<String> {
+    Path::new(file_name)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+}
+
+/// Checks whether an attached file is a WhatsApp voice note (named like `PTT-20230101-WA0001.opus`)
+fn is_voice_note(file_name: &str) -> bool {
+    file_name.to_lowercase().starts_with("ptt-")
+        && file_extension(file_name).is_some_and(|ext| AUDIO_TYPES.contains(&ext.as_str()))
+}
+
+/// Gets the media type of each of `PHOTO_TYPES`, `VIDEO_TYPES` and `AUDIO_TYPES`, for looking up with `media_type`
+fn media_extension_types() -> HashMap<&'static str, MediaType> {
+    PHOTO_TYPES
+        .iter()
+        .map(|ext| (*ext, MediaType::PHOTO))
+        .chain(VIDEO_TYPES.iter().map(|ext| (*ext, MediaType::VIDEO)))
+        .chain(AUDIO_TYPES.iter().map(|ext| (*ext, MediaType::AUDIO)))
+        .collect()
+}
+
+/// Gets the type of an attached file from its extension
+/// # Parameters
+/// * `file_name` - Name of the file
+/// * `extension_types` - Media type of each known extension, as returned by `media_extension_types`
+fn media_type(file_name: &str, extension_types: &HashMap<&str, MediaType>) -> MediaType {
+    file_extension(file_name)
+        .and_then(|ext| extension_types.get(ext.as_str()).copied())
+        .unwrap_or(MediaType::OTHER)
+}
+
+/// Files in a media directory, indexed by the names attachments can be resolved with (see `full_file_path`)
+#[derive(Default)]
+struct DirectoryFiles {
+    /// Mapping of file names to their full paths
+    by_name: HashMap<String, PathBuf>,
+    /// Mapping of the ends of file names following a separator (e.g. `IMG-20220101-WA0001.jpg` in
+    /// `Chat - IMG-20220101-WA0001.jpg`) to the full paths of the files they end
+    by_suffix: HashMap<String, Vec<PathBuf>>,
+    /// Mapping of WhatsApp media IDs and file extensions to the full paths of the files with them
+    by_media_id: HashMap<(String, Option<String>), Vec<PathBuf>>,
+}
+
+impl DirectoryFiles {
+    /// Indexes the files in `by_name`, a mapping of file names to their full paths
+    fn new(by_name: HashMap<String, PathBuf>) -> DirectoryFiles {
+        let mut by_suffix: HashMap<String, Vec<PathBuf>> = HashMap::new();
+        let mut by_media_id: HashMap<(String, Option<String>), Vec<PathBuf>> = HashMap::new();
+        for (name, path) in by_name.iter() {
+            for (i, _) in name.match_indices([' ', '-', '_']) {
+                by_suffix
+                    .entry(name[i + 1..].to_owned())
+                    .or_default()
+                    .push(path.clone());
+            }
+            if let Some(id) = media_id(name) {
+                by_media_id
+                    .entry((id.to_owned(), file_extension(name)))
+                    .or_default()
+                    .push(path.clone());
+            }
+        }
+        DirectoryFiles {
+            by_name,
+            by_suffix,
+            by_media_id,
+        }
+    }
+}
+
+/// Gets the files in `directory` and its subdirectories (up to `MEDIA_DIRECTORY_MAX_DEPTH` deep), if any
+///
+/// Returns the files, along with the names of any files found in more than one folder. For those, the shallowest
+/// one (and then the first one alphabetically) is used.
+fn list_directory_files(directory: &Option<String>) -> (DirectoryFiles, Vec<String>) {
+    let mut directory_files = HashMap::new();
+    let mut duplicates = Vec::new();
+    let Some(dir) = directory else {
+        return (DirectoryFiles::default(), duplicates);
+    };
+    // Breadth first, so that shallower files are seen first
+    let mut to_visit = VecDeque::from([(PathBuf::from(dir), 0)]);
+    while let Some((dir, depth)) = to_visit.pop_front() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut paths: Vec<PathBuf> = entries.filter_map(|e| e.ok().map(|e| e.path())).collect();
+        paths.sort();
+        for path in paths {
+            if path.is_dir() {
+                if depth < MEDIA_DIRECTORY_MAX_DEPTH {
+                    to_visit.push_back((path, depth + 1));
+                }
+            } else if let Some(file_name) = path.file_name() {
+                let file_name = file_name.to_string_lossy().into_owned();
+                match directory_files.entry(file_name) {
+                    Entry::Occupied(e) => duplicates.push(e.key().clone()),
+                    Entry::Vacant(e) => {
+                        e.insert(path);
+                    }
+                }
+            }
+        }
+    }
+    (DirectoryFiles::new(directory_files), duplicates)
+}
+
+/// Gets the WhatsApp media ID (e.g. `WA0001`) in a file name, if any
+fn media_id(file_name: &str) -> Option<&str> {
+    file_name.match_indices("WA").find_map(|(i, _)| {
+        let id = file_name.get(i..i + 6)?;
+        id[2..].chars().all(|c| c.is_ascii_digit()).then_some(id)
+    })
+}
+
+/// Searches the media directory for a file named `path`; if one is found, the full string path
+///
+/// Some backup tools rename media files (e.g. by adding the chat name as a prefix), so if there's no exact match
+/// this falls back to a file whose name ends with `path` after a separator, and then to a file with the same
+/// extension and WhatsApp media ID. A fallback is only used if it matches a single file; if it matches several,
+/// `path` is added to `ambiguous` instead.
+/// # Parameters
+/// * `path` - Name of the file
+/// * `directory_files` - Files in the media directory (see `list_directory_files`)
+/// * `ambiguous` - File names that matched more than one file
+fn full_file_path(
+    path: &str,
+    directory_files: &DirectoryFiles,
+    ambiguous: &mut Vec<String>,
+) -> Option<String> {
+    if path.is_empty() {
+        return None;
+    }
+    let full_path = match directory_files.by_name.get(path) {
+        Some(full_path) => full_path,
+        None => {
+            let candidates = directory_files.by_suffix.get(path).or_else(|| {
+                let id = media_id(path)?;
+                directory_files
+                    .by_media_id
+                    .get(&(id.to_owned(), file_extension(path)))
+            });
+            match candidates.map(Vec::as_slice) {
+                Some([only]) => only,
+                None | Some([]) => return None,
+                Some(_) => {
+                    ambiguous.push(path.to_owned());
+                    return None;
+                }
+            }
+        }
+    };
+    Some(full_path.to_string_lossy().into_owned())
+}
+
+/// Gets the name to use for a sender
+///
+/// If `merge_case` is set, names differing only in case are all given the casing that was seen first.
+/// # Parameters
+/// * `sender_casing` - Mapping of lowercased sender names to the casing seen first
+/// * `merge_case` - Whether to merge names differing only in case
+/// * `sender` - Sender name as it appears in the export
+fn canonical_sender(
+    sender_casing: &mut HashMap<String, String>,
+    merge_case: bool,
+    sender: &str,
+) -> String {
+    if !merge_case {
+        return sender.to_owned();
+    }
+    sender_casing
+        .entry(sender.to_lowercase())
+        .or_insert_with(|| sender.to_owned())
+        .clone()
+}
+
+/// Gets who performed the event described by a system message, e.g. Alice in "Alice added Bob"
+///
+/// System messages start with the name of a person, but that person isn't always the one who performed the event:
+/// in "Alice was added" or "Alice's security code changed" it happened to them instead, so no one is returned.
+/// # Parameters
+/// * `content` - Text of the system message
+/// * `senders` - Known senders
+fn system_message_actor(content: &str, senders: &HashSet<String>) -> Option<String> {
+    // The longest matching name is used so that e.g. "Al" doesn't match "Alice"
+    let sender = senders
+        .iter()
+        .filter(|s| {
+            content
+                .strip_prefix(s.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with([' ', '\'', '’', ':', ',']))
+        })
+        .max_by_key(|s| s.len())?;
+    let rest = content[sender.len()..].to_lowercase();
+    let is_subject = rest.starts_with("'s ")
+        || rest.starts_with("’s ")
+        || [
+            " was ",
+            " were ",
+            " has been ",
+            " have been ",
+            " is no longer ",
+            " is now ",
+        ]
+        .iter()
+        .any(|passive| rest.starts_with(passive));
+    if is_subject {
+        None
+    } else {
+        Some(sender.to_owned())
+    }
+}
+
+/// Checks whether a message is automated, i.e. its text contains one of `patterns` (ignoring case)
+///
+/// WhatsApp Business accounts can send automated greeting and away messages, which are exported like any other
+/// message; the patterns are how they're recognized.
+/// # Parameters
+/// * `content` - Content of the message
+/// * `patterns` - Patterns identifying automated messages
+fn is_automated(content: &MessageContent, patterns: &[String]) -> bool {
+    match content {
+        MessageContent::Text(text) if !patterns.is_empty() => {
+            let lower = text.to_lowercase();
+            patterns
+                .iter()
+                .any(|p| !p.is_empty() && lower.contains(&p.to_lowercase()))
+        }
+        _ => false,
+    }
+}
+
+/// Gets the event described by a system message, if it's one of `SYSTEM_EVENT_PHRASES` or a change in membership
+/// (see `membership_change`)
+/// # Parameters
+/// * `content` - Content of the message
+/// * `senders` - Known senders
+fn system_event(content: &MessageContent, senders: &HashSet<String>) -> Option<SystemEvent> {
+    let MessageContent::System(text) = content else {
+        return None;
+    };
+    let lower = text.to_lowercase();
+    SYSTEM_EVENT_PHRASES
+        .iter()
+        .find(|(phrase, _)| lower.contains(phrase))
+        .map(|(_, event)| *event)
+        .or_else(|| membership_change(text, senders).map(|(event, _)| event))
+}
+
+/// Gets who joined or left a group from a system message (such as "Alice added Bob and Carol" or "You were added"),
+/// along with how
+///
+/// Only English exports are recognized. "You" is returned as-is, rather than as the chat's "you". Whoever added or
+/// removed someone (or created the group) must be "You" or one of `senders`, so that a name or other text containing
+/// e.g. " added " isn't mistaken for the event.
+/// # Parameters
+/// * `text` - Text of the system message
+/// * `senders` - Known senders
+fn membership_change(text: &str, senders: &HashSet<String>) -> Option<(SystemEvent, Vec<String>)> {
+    let text = text.trim().trim_end_matches('.');
+    // The longest matching name is used so that e.g. "Al" doesn't match "Alice"
+    let actor = std::iter::once("You")
+        .chain(senders.iter().map(String::as_str))
+        .filter(|a| {
+            text.strip_prefix(a)
+                .is_some_and(|rest| rest.starts_with(' '))
+        })
+        .max_by_key(|a| a.len());
+    let after_actor = actor.map(|a| &text[a.len() + 1..]);
+    // Lists of names look like "Bob, Carol and Dave"
+    let names = |list: &str| -> Vec<String> {
+        list.replace(" and ", ", ")
+            .split(", ")
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+            .map(|n| {
+                if n == "you" {
+                    "You".to_owned()
+                } else {
+                    n.to_owned()
+                }
+            })
+            .collect()
+    };
+    // Names in messages about joining and leaving aren't anchored to a known sender, so quoted text (such as a new
+    // group subject) is ruled out instead
+    let joined = JOINED_SUFFIXES
+        .iter()
+        .find_map(|suffix| text.strip_suffix(suffix))
+        .filter(|member| !member.contains(['"', '“', ':']));
+    let left = text
+        .strip_suffix(" left")
+        .filter(|member| !member.contains(['"', '“', ':']));
+    let (event, members) = if text == "You were added" {
+        (SystemEvent::ADDED, vec!["You".to_owned()])
+    } else if text == "You were removed" {
+        (SystemEvent::REMOVED, vec!["You".to_owned()])
+    } else if let Some(member) = left {
+        (SystemEvent::LEFT, names(member))
+    } else if let Some(member) = joined {
+        (SystemEvent::JOINED, names(member))
+    } else if let (Some(actor), Some(true)) = (
+        actor,
+        after_actor.map(|rest| rest.starts_with("created group ")),
+    ) {
+        (SystemEvent::JOINED, names(actor))
+    } else if let Some(added) = after_actor.and_then(|rest| rest.strip_prefix("added ")) {
+        (SystemEvent::ADDED, names(added))
+    } else if let Some(removed) = after_actor.and_then(|rest| rest.strip_prefix("removed ")) {
+        (SystemEvent::REMOVED, names(removed))
+    } else {
+        return None;
+    };
+    if members.is_empty() {
+        None
+    } else {
+        Some((event, members))
+    }
+}
+
+/// Gets the content of a text message, recognizing notices that the message was deleted
+/// # Parameters
+/// * `text` - Text of the message
+/// * `sender` - Sender of the message
+/// * `you` - Senders who are "you"
+fn text_or_deleted(text: &str, sender: &str, you: &[String]) -> MessageContent {
+    let notice = text
+        .trim()
+        .trim_start_matches('<')
+        .trim_end_matches(['>', '.']);
+    let lower_notice = notice.to_lowercase();
+    let sent_by_you = you.iter().any(|y| y == sender);
+    if YOU_DELETED_NOTICES.contains(&lower_notice.as_str()) {
+        MessageContent::Deleted(Deletion {
+            deleted_by_you: true,
+            deleted_by_admin: None,
+        })
+    } else if DELETED_NOTICES.contains(&lower_notice.as_str()) {
+        MessageContent::Deleted(Deletion {
+            deleted_by_you: sent_by_you,
+            deleted_by_admin: None,
+        })
+    } else if lower_notice.starts_with(ADMIN_DELETED_PREFIX) {
+        MessageContent::Deleted(Deletion {
+            deleted_by_you: false,
+            deleted_by_admin: notice.get(ADMIN_DELETED_PREFIX.len()..).map(str::to_owned),
+        })
+    } else {
+        MessageContent::Text(text.to_owned())
+    }
+}
+
+/// Removes an "edited" notice from the end of a message
+///
+/// Returns the remaining text and whether a notice was found
+fn strip_edited_notice(text: &str) -> (&str, bool) {
+    for notice in EDITED_NOTICES {
+        if let Some(stripped) = text.trim_end().strip_suffix(notice) {
+            return (stripped.trim_end(), true);
+        }
+    }
+    (text, false)
+}
+
+/// Parses a message timestamp, trying `formats[*current]` first and then each of the others in order
+///
+/// `current` is updated to the index of the format that worked, so that once a fallback format is needed, it's
+/// tried first for the rest of the chat.
+fn parse_timestamp(text: &str, formats: &[&str], current: &mut usize) -> Option<NaiveDateTime> {
+    let start = *current;
+    for i in std::iter::once(start).chain((0..formats.len()).filter(|&i| i != start)) {
+        if let Ok(timestamp) = NaiveDateTime::parse_from_str(text, formats[i]) {
+            *current = i;
+            return Some(timestamp);
+        }
+    }
+    None
+}
+
+/// Appends a line that continues the previous message to that message
+///
+/// A line consisting only of an "edited" notice marks the previous message as edited instead of being appended.
+/// # Parameters
+/// * `messages` - Messages parsed so far
+/// * `line` - Continuation line
+/// * `line_number` - Line number of `line` in the chat file
+/// * `blank_lines` - Number of blank lines preceding `line`, which are preserved as paragraph breaks
+/// * `captions` - Whether the line may be added to the caption of a media message
+fn append_continuation(
+    messages: &mut [Message],
+    line: &str,
+    line_number: usize,
+    blank_lines: usize,
+    captions: bool,
+) {
+    if let Some(last_msg) = messages.last_mut() {
+        let (line, edited) = strip_edited_notice(line);
+        last_msg.edited |= edited;
+        // A notice on its own line marks the message as edited whatever kind of message it is
+        if edited && line.is_empty() {
+            last_msg.source_lines.end = line_number + 1;
+            return;
+        }
+        let separator = "\n".repeat(blank_lines + 1);
+        match &mut last_msg.content {
+            MessageContent::Text(text) => {
+                text.push_str(&separator);
+                text.push_str(line);
+            }
+            MessageContent::Media(media) if captions => {
+                media.caption = Some(match media.caption.take() {
+                    Some(old_caption) => old_caption + &separator + line,
+                    None => line.to_owned(),
+                });
+            }
+            _ => return,
+        }
+        last_msg.source_lines.end = line_number + 1;
+    }
+}
+
+/// Checks whether a line looks like the first line of a message in either export version
+/// # Parameters
+/// * `line` - Line to check
+fn looks_like_chat_line(line: &str) -> bool {
+    let line = line.replace('\u{200e}', "");
+    let line = line.trim();
+    let mut timestamp_format = 0;
+    match line.strip_prefix('[') {
+        Some(rest) => rest.find("] ").is_some_and(|end| {
+            parse_timestamp(&rest[..end], &OLD_TIMESTAMP_FORMATS, &mut timestamp_format).is_some()
+        }),
+        None => line.find(" - ").is_some_and(|end| {
+            end <= 20
+                && parse_timestamp(&line[..end], &NEW_TIMESTAMP_FORMATS, &mut timestamp_format)
+                    .is_some()
+        }),
+    }
+}
+
+/// Reader that counts the bytes read through it
+struct CountingReader<R> {
+    /// Reader being counted
+    inner: R,
+    /// Number of bytes read so far
+    count: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.fetch_add(n as u64, Relaxed);
+        Ok(n)
+    }
+}
+
+/// A chat file opened for reading (see `open_chat_file`)
+struct ChatFile {
+    /// Reader of the file's text, as UTF-8
+    reader: Box<dyn BufRead>,
+    /// Size of the file in bytes, after decompressing it
+    total_bytes: u64,
+    /// Number of those bytes read so far; since they're counted before transcoding, this can be compared with
+    /// `total_bytes` to measure progress
+    bytes_read: Arc<AtomicU64>,
+}
+
+/// Opens a chat file for reading, transparently decompressing it if it's gzipped and transcoding it to UTF-8
+/// # Parameters
+/// * `path` - Path of the chat file
+/// * `encoding` - Label of the encoding of the file; if not given, the file is assumed to be UTF-8 unless it starts
+///   with a byte order mark
+fn open_chat_file(path: &str, encoding: Option<&str>) -> Result<ChatFile, String> {
+    let encoding = match encoding {
+        Some(label) => Some(
+            Encoding::for_label(label.trim().as_bytes())
+                .ok_or(format!("Unknown encoding: {0}", label))?,
+        ),
+        None => None,
+    };
+    let decoder = DecodeReaderBytesBuilder::new()
+        .encoding(encoding)
+        .strip_bom(true)
+        .clone();
+    let mut file = File::open(path).or(Err("Error opening file"))?;
+    let mut magic = [0; 2];
+    let is_gzip = path.to_lowercase().ends_with(".gz")
+        || (file.read_exact(&mut magic).is_ok() && magic == GZIP_MAGIC);
+    if is_gzip {
+        // The last four bytes of a gzip file are its uncompressed size (modulo 2^32)
+        let mut size = [0; 4];
+        let total_bytes = match file
+            .seek(SeekFrom::End(-4))
+            .and_then(|_| file.read_exact(&mut size))
+        {
+            Ok(_) => u32::from_le_bytes(size) as u64,
+            Err(_) => 0,
+        };
+        file.seek(SeekFrom::Start(0))
+            .or(Err("Error reading file"))?;
+        let count = Arc::new(AtomicU64::new(0));
+        let reader = CountingReader {
+            inner: MultiGzDecoder::new(file),
+            count: Arc::clone(&count),
+        };
+        Ok(ChatFile {
+            reader: Box::new(BufReader::new(decoder.build(reader))),
+            total_bytes,
+            bytes_read: count,
+        })
+    } else {
+        let total_bytes = file.metadata().map(|m| m.len()).unwrap_or(0);
+        file.seek(SeekFrom::Start(0))
+            .or(Err("Error reading file"))?;
+        let count = Arc::new(AtomicU64::new(0));
+        let reader = CountingReader {
+            inner: file,
+            count: Arc::clone(&count),
+        };
+        Ok(ChatFile {
+            reader: Box::new(BufReader::new(decoder.build(reader))),
+            total_bytes,
+            bytes_read: count,
+        })
+    }
+}
+
+/// Parses a WhatsApp chat export
+/// # Parameters
+/// * `chat` - Chat to load, including the path to the chat file
+/// * `on_progress` - Called with the number of bytes read so far and the total number of bytes, at most once per percent
+/// * `cancel` - Set to stop parsing early, in which case `LOAD_CANCELLED` is returned
+fn parse_whatsapp_export<F: FnMut(u64, u64)>(
+    chat: &ChatToLoad,
+    mut on_progress: F,
+    cancel: &AtomicBool,
+) -> Result<ParsedWhatsAppChat, String> {
+    let path = &chat.file;
+    let directory = &chat.directory;
+    let ChatFile {
+        mut reader,
+        total_bytes,
+        bytes_read,
+    } = open_chat_file(path, chat.encoding.as_deref())?;
+    // Guard against a large file (such as a video) being selected by mistake
+    let max_file_size = chat.max_file_size.unwrap_or(DEFAULT_MAX_CHAT_FILE_SIZE);
+    if total_bytes > max_file_size {
+        let first_reader = open_chat_file(path, chat.encoding.as_deref())?.reader;
+        let mut first_line = Vec::new();
+        first_reader
+            .take(MAX_FIRST_LINE)
+            .read_until(b'\n', &mut first_line)
+            .or(Err("Error reading file"))?;
+        if !looks_like_chat_line(&String::from_utf8_lossy(&first_line)) {
+            return Err(format!(
+                "The file is {0} MB, which is larger than the {1} MB limit, and doesn't look like a WhatsApp chat export; if it is one, raise the maximum file size to load it",
+                total_bytes / (1024 * 1024),
+                max_file_size / (1024 * 1024)
+            ));
+        }
+    }
+    // Number of lines that weren't valid UTF-8, and were read as Windows-1252 instead
+    let mut legacy_lines = 0;
+    let mut buf = Vec::new();
+    let mut line_number = 0;
+    let mut last_percent = 0;
+    // The version is only detected if it isn't given
+    let mut first = chat.format_hint.is_none();
+    // Blank lines are only kept if they turn out to be inside a multi-line message
+    let mut pending_blank_lines = 0;
+    let mut version = chat.format_hint.unwrap_or(ExportVersion::NEW);
+    // Index of the timestamp format in use, and of the first one used
+    let mut timestamp_format = 0;
+    let mut first_timestamp_format = None;
+    // Timestamp text of each message, so they can be read again if the format changes partway through
+    let mut timestamp_texts: Vec<String> = Vec::new();
+    let mut messages: Vec<Message> = Vec::new();
+    let mut senders: HashSet<String> = HashSet::with_capacity(2);
+    // Mapping of lowercased sender names to their first-seen casing
+    let mut sender_casing: HashMap<String, String> = HashMap::new();
+    let (directory_files, duplicate_files) = list_directory_files(directory);
+    let extension_types = media_extension_types();
+    let mut ambiguous_media = Vec::new();
+    let mut warnings = Vec::new();
+    if !duplicate_files.is_empty() {
+        warnings.push(format!(
+            "{0} media files appear in more than one folder of the media directory, including {1}; the shallowest one is used.",
+            duplicate_files.len(),
+            duplicate_files[0]
+        ));
+    }
+    let truncated_warning = |l: &str| {
+        format!(
+            "The last line of the chat appears to be incomplete and was skipped: {0}",
+            l
+        )
+    };
+    loop {
+        if cancel.load(Relaxed) {
+            return Err(LOAD_CANCELLED.to_owned());
+        }
+        buf.clear();
+        match reader.read_until(b'\n', &mut buf) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(err) => return Err(format!("Error reading file: {0}", err)),
+        }
+        line_number += 1;
+        // The reader reads ahead, so this can briefly be more than has been parsed
+        let bytes_read = bytes_read.load(Relaxed).min(total_bytes);
+        if let Some(percent) = (bytes_read * 100).checked_div(total_bytes) {
+            if percent > last_percent {
+                last_percent = percent;
+                on_progress(bytes_read, total_bytes);
+            }
+        }
+        // A final line without a newline may have been cut off, so failing to parse it isn't fatal
+        let truncated = !buf.ends_with(b"\n");
+        let line = match std::str::from_utf8(&buf) {
+            Ok(l) => Ok(l.to_owned()),
+            // Without a given encoding, invalid lines are probably from an older export in a legacy encoding
+            Err(_) if chat.encoding.is_none() && !truncated => {
+                legacy_lines += 1;
+                Ok(WINDOWS_1252
+                    .decode_without_bom_handling(&buf)
+                    .0
+                    .into_owned())
+            }
+            Err(e) => Err(e),
+        };
+        match line {
+            Ok(l) => {
+                let l = l.replace('\u{200e}', "").trim().to_owned();
+                if l.is_empty() {
+                    pending_blank_lines += 1;
+                    continue;
+                }
+                let blank_lines = std::mem::take(&mut pending_blank_lines);
+                if first {
+                    if l.chars().next().unwrap_or(' ') == '[' {
+                        version = ExportVersion::OLD;
+                    }
+                    first = false;
+                }
+                match version {
+                    ExportVersion::OLD => {
+                        // If the message doesn't start with a open square bracket, it's a continuation of the previous message
+                        if !l.starts_with('[') {
+                            append_continuation(&mut messages, &l, line_number, blank_lines, false);
+                        }
+                        // Otherwise it's the start of a normal message
+                        else {
+                            // Get the end time
+                            let time_end_idx = match l.find("] ") {
+                                Some(idx) => idx,
+                                None if truncated => {
+                                    warnings.push(truncated_warning(&l));
+                                    break;
+                                }
+                                None => return Err("Failed to find time end".to_owned()),
+                            };
+                            let timestamp = match parse_timestamp(
+                                &l[1..time_end_idx],
+                                &OLD_TIMESTAMP_FORMATS,
+                                &mut timestamp_format,
+                            ) {
+                                Some(timestamp) => timestamp,
+                                None if truncated => {
+                                    warnings.push(truncated_warning(&l));
+                                    break;
+                                }
+                                None => {
+                                    return Err(format!(
+                                        "Failed to parse time: {0}",
+                                        &l[1..time_end_idx]
+                                    ))
+                                }
+                            };
+                            first_timestamp_format.get_or_insert(timestamp_format);
+                            timestamp_texts.truncate(messages.len());
+                            timestamp_texts.push(l[1..time_end_idx].to_owned());
+                            if let Some(col_i) = l[time_end_idx + 2..].find(": ") {
+                                let colon_idx = col_i + time_end_idx + 2;
+                                let sender = canonical_sender(
+                                    &mut sender_casing,
+                                    chat.merge_sender_case,
+                                    &l[time_end_idx + 2..colon_idx],
+                                );
+                                senders.insert(sender.clone());
+                                if l[colon_idx + 2..].trim() == "audio omitted" {
+                                    messages.push(Message {
+                                        timestamp,
+                                        sender: Some(sender),
+                                        content: MessageContent::Media(Media {
+                                            media_type: MediaType::AUDIO,
+                                            path: None,
+                                            file_name: None,
+                                            caption: None,
+                                            voice_note: true,
+                                        }),
+                                        starred: AtomicBool::new(false),
+                                        idx: messages.len(),
+                                        edited: false,
+                                        automated: false,
+                                        source_lines: line_number..line_number + 1,
+                                        system_event: None,
+                                    });
+                                } else if l.contains("<attached: ") {
+                                    let name_start = l.find("<attached: ").unwrap() + 11;
+                                    // The file name ends at the matching `>`; anything after it is a caption
+                                    let name_end = l[name_start..]
+                                        .find('>')
+                                        .map_or(l.len(), |i| name_start + i);
+                                    let file_name = &l[name_start..name_end];
+                                    let caption = l
+                                        .get(name_end + 1..)
+                                        .map(str::trim)
+                                        .filter(|c| !c.is_empty())
+                                        .map(str::to_owned);
+                                    let media_type = media_type(file_name, &extension_types);
+                                    messages.push(Message {
+                                        timestamp,
+                                        sender: Some(sender),
+                                        content: MessageContent::Media(Media {
+                                            media_type,
+                                            path: full_file_path(
+                                                file_name,
+                                                &directory_files,
+                                                &mut ambiguous_media,
+                                            ),
+                                            file_name: Some(file_name.to_owned()),
+                                            caption,
+                                            voice_note: is_voice_note(file_name),
+                                        }),
+                                        starred: AtomicBool::new(false),
+                                        idx: messages.len(),
+                                        edited: false,
+                                        automated: false,
+                                        source_lines: line_number..line_number + 1,
+                                        system_event: None,
+                                    });
+                                } else {
+                                    let (text, edited) = strip_edited_notice(&l[colon_idx + 2..]);
+                                    let content = text_or_deleted(text, &sender, &chat.you);
+                                    messages.push(Message {
+                                        timestamp,
+                                        sender: Some(sender),
+                                        content,
+                                        starred: AtomicBool::new(false),
+                                        idx: messages.len(),
+                                        edited,
+                                        automated: false,
+                                        source_lines: line_number..line_number + 1,
+                                        system_event: None,
+                                    });
+                                }
+                            }
+                            // Handle "system" messages
+                            else {
+                                // Icon messages aren't included in the "new" exports, which can hinder matching them up
+                                if !l[time_end_idx + 2..].ends_with("icon") {
+                                    // They probably start with a previous user's name
+                                    let sender =
+                                        system_message_actor(&l[time_end_idx + 2..], &senders);
+                                    messages.push(Message {
+                                        timestamp,
+                                        sender,
+                                        content: MessageContent::System(
+                                            l[time_end_idx + 2..].to_string(),
+                                        ),
+                                        starred: AtomicBool::new(false),
+                                        idx: messages.len(),
+                                        edited: false,
+                                        automated: false,
+                                        source_lines: line_number..line_number + 1,
+                                        system_event: None,
+                                    });
+                                }
+                            }
+                        }
+                    }
+                    ExportVersion::NEW => {
+                        // Find the index of the " - " between the time and <name>
+                        if let Some(time_end) = l.find(" - ") {
+                            if time_end <= 20 {
+                                let timestamp = match parse_timestamp(
+                                    &l[..time_end],
+                                    &NEW_TIMESTAMP_FORMATS,
+                                    &mut timestamp_format,
+                                ) {
+                                    Some(timestamp) => timestamp,
+                                    // Unless it looks like a 12-hour time, it's probably just a dash in a continuation
+                                    None if !l[..time_end].ends_with('M') => {
+                                        append_continuation(
+                                            &mut messages,
+                                            &l,
+                                            line_number,
+                                            blank_lines,
+                                            !l.contains("M - "),
+                                        );
+                                        continue;
+                                    }
+                                    None if truncated => {
+                                        warnings.push(truncated_warning(&l));
+                                        break;
+                                    }
+                                    None => {
+                                        return Err(format!(
+                                            "Failed to parse time: {0}",
+                                            &l[..time_end]
+                                        ))
+                                    }
+                                };
+                                first_timestamp_format.get_or_insert(timestamp_format);
+                                timestamp_texts.truncate(messages.len());
+                                timestamp_texts.push(l[..time_end].to_owned());
+                                if let Some(col_i) = l[time_end + 3..].find(": ") {
+                                    let colon_idx = col_i + time_end + 3;
+                                    let sender = canonical_sender(
+                                        &mut sender_casing,
+                                        chat.merge_sender_case,
+                                        &l[time_end + 3..colon_idx],
+                                    );
+                                    senders.insert(sender.clone());
+                                    if l.contains("<Media omitted") {
+                                        messages.push(Message {
+                                            timestamp,
+                                            sender: Some(sender),
+                                            content: MessageContent::Media(Media {
+                                                media_type: MediaType::OTHER,
+                                                path: None,
+                                                file_name: None,
+                                                caption: None,
+                                                voice_note: false,
+                                            }),
+                                            starred: AtomicBool::new(false),
+                                            idx: messages.len(),
+                                            edited: false,
+                                            automated: false,
+                                            source_lines: line_number..line_number + 1,
+                                            system_event: None,
+                                        });
+                                    } else if l.ends_with("(file attached)") {
+                                        let file_name = &l[colon_idx + 2..l.len() - 16];
+                                        let media_type = media_type(file_name, &extension_types);
+                                        messages.push(Message {
+                                            timestamp,
+                                            sender: Some(sender),
+                                            content: MessageContent::Media(Media {
+                                                media_type,
+                                                path: full_file_path(
+                                                    file_name,
+                                                    &directory_files,
+                                                    &mut ambiguous_media,
+                                                ),
+                                                file_name: Some(file_name.to_owned()),
+                                                caption: None,
+                                                voice_note: is_voice_note(file_name),
+                                            }),
+                                            starred: AtomicBool::new(false),
+                                            idx: messages.len(),
+                                            edited: false,
+                                            automated: false,
+                                            source_lines: line_number..line_number + 1,
+                                            system_event: None,
+                                        });
+                                    } else if l[colon_idx + 2..].to_string().trim() != "null" {
+                                        let (text, edited) =
+                                            strip_edited_notice(&l[colon_idx + 2..]);
+                                        let content = text_or_deleted(text, &sender, &chat.you);
+                                        messages.push(Message {
+                                            timestamp,
+                                            sender: Some(sender),
+                                            content,
+                                            starred: AtomicBool::new(false),
+                                            idx: messages.len(),
+                                            edited,
+                                            automated: false,
+                                            source_lines: line_number..line_number + 1,
+                                            system_event: None,
+                                        });
+                                    }
+                                }
+                                // Handle "system" messages
+                                else {
+                                    // They probably start with a previous user's name
+                                    let sender = system_message_actor(&l[time_end + 3..], &senders);
+                                    messages.push(Message {
+                                        timestamp,
+                                        sender,
+                                        content: MessageContent::System(
+                                            l[time_end + 3..].to_string(),
+                                        ),
+                                        starred: AtomicBool::new(false),
+                                        idx: messages.len(),
+                                        edited: false,
+                                        automated: false,
+                                        source_lines: line_number..line_number + 1,
+                                        system_event: None,
+                                    });
+                                }
+                            }
+                            // If the dash is not in the first 20 characters, it's not part of the message time
+                            else {
+                                append_continuation(
+                                    &mut messages,
+                                    &l,
+                                    line_number,
+                                    blank_lines,
+                                    !l.contains("M - "),
+                                );
+                            }
+                        }
+                        // If there is no match, it's probably a continuation of the previous message
+                        else {
+                            append_continuation(&mut messages, &l, line_number, blank_lines, true);
+                        }
+                    }
+                }
+            }
+            // A truncated final line may end part way through a character
+            Err(_) if truncated => {
+                warnings.push(truncated_warning(&String::from_utf8_lossy(&buf)));
+            }
+            Err(_) => {}
+        }
+    }
+    if legacy_lines > 0 {
+        warnings.push(format!(
+            "{0} lines weren't valid UTF-8 and were read as Windows-1252; if any text looks garbled, try setting the chat's encoding.",
+            legacy_lines
+        ));
+    }
+    let timestamp_formats = match version {
+        ExportVersion::OLD => &OLD_TIMESTAMP_FORMATS[..],
+        ExportVersion::NEW => &NEW_TIMESTAMP_FORMATS[..],
+    };
+    // A different format is only switched to once a timestamp doesn't match the one in use (e.g. the first day
+    // after the 12th in a day-first export), so the messages before then are read again with the final format
+    let mut unreadable_timestamps = 0;
+    if first_timestamp_format.is_some_and(|f| f != timestamp_format) {
+        for (m, text) in messages.iter_mut().zip(timestamp_texts.iter()) {
+            match NaiveDateTime::parse_from_str(text, timestamp_formats[timestamp_format]) {
+                Ok(timestamp) => m.timestamp = timestamp,
+                Err(_) => unreadable_timestamps += 1,
+            }
+        }
+    }
+    if timestamp_format != 0 {
+        warnings.push(format!(
+            "Timestamps didn't match the usual format ({0}), so they were read as {1}{2}.",
+            timestamp_formats[0],
+            timestamp_formats[timestamp_format],
+            if unreadable_timestamps == 0 {
+                String::new()
+            } else {
+                format!(
+                    "; {0} messages didn't match it and may have the wrong timestamp",
+                    unreadable_timestamps
+                )
+            }
+        ));
+    }
+    if !ambiguous_media.is_empty() {
+        warnings.push(format!(
+            "{0} media files matched more than one file in the media directory and weren't resolved, including {1}",
+            ambiguous_media.len(),
+            ambiguous_media[0]
+        ));
+    }
+    for m in messages.iter_mut() {
+        m.automated = is_automated(&m.content, &chat.automated_patterns);
+        m.system_event = system_event(&m.content, &senders);
+    }
+    // Senders who first appear after a system message about them weren't known when it was parsed
+    for m in messages.iter_mut() {
+        if m.sender.is_none() {
+            if let MessageContent::System(content) = &m.content {
+                m.sender = system_message_actor(content, &senders);
+            }
+        }
+    }
+    // Shift to a common basis so chats exported in different timezones interleave correctly
+    if let Some(offset) = chat.tz_offset_minutes {
+        for m in messages.iter_mut() {
+            m.timestamp += Duration::minutes(offset.into());
+        }
+    }
+    // Timestamps only have minute precision in newer exports, so the sort is stable to keep messages sent in the same
+    // minute (such as several photos) in the order they were sent. Captions were already merged into their media
+    // while parsing, so they stay together.
+    messages.sort_by_key(|m| m.timestamp);
+    let timestamp_precision = if timestamp_formats[timestamp_format].contains("%S") {
+        TimestampPrecision::SECOND
+    } else {
+        TimestampPrecision::MINUTE
+    };
+    let shared_timestamps = messages
+        .windows(2)
+        .filter(|w| w[0].timestamp == w[1].timestamp)
+        .count();
+    if !messages.is_empty()
+        && shared_timestamps as f64 / messages.len() as f64 > DUPLICATE_TIMESTAMP_WARNING_FRACTION
+    {
+        warnings.push(format!(
+            "{0} of {1} messages have the same timestamp as the previous message{2}, so their order relies on the order in the file.",
+            shared_timestamps,
+            messages.len(),
+            match timestamp_precision {
+                TimestampPrecision::MINUTE => " (this export doesn't include seconds)",
+                TimestampPrecision::SECOND => "",
+            }
+        ));
+    }
+    // Indices must match positions after sorting, since that's how messages are starred
+    for (i, m) in messages.iter_mut().enumerate() {
+        m.idx = i;
+    }
+    for idx in chat.starred.iter() {
+        if let Some(m) = messages.get(*idx) {
+            m.starred.store(true, Relaxed);
+        } else {
+            warnings.push(
+                "Some starred indices not found; these messages have not been stared.".to_owned(),
+            );
+        }
+    }
+    Ok(ParsedWhatsAppChat {
+        warnings,
+        chat: WhatsAppChat::from_parsed(
+            chat,
+            messages,
+            timestamp_precision,
+            timestamp_formats[timestamp_format].to_owned(),
+        ),
+    })
+}
+
+/// Gets what a chat would be parsed from, to check whether a cached parse of it is still valid
+///
+/// Returns `None` if the chat file can't be read.
+fn chat_cache_key(chat: &ChatToLoad) -> Option<ChatCacheKey> {
+    let metadata = fs::metadata(&chat.file).ok()?;
+    Some(ChatCacheKey {
+        cache_version: CHAT_CACHE_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_owned(),
+        modified: metadata.modified().ok()?,
+        size: metadata.len(),
+        directory: chat.directory.clone(),
+        directory_modified: chat
+            .directory
+            .as_ref()
+            .and_then(|d| fs::metadata(d).and_then(|m| m.modified()).ok()),
+        you: chat.you.clone(),
+        tz_offset_minutes: chat.tz_offset_minutes,
+        merge_sender_case: chat.merge_sender_case,
+        automated_patterns: chat.automated_patterns.clone(),
+        format_hint: chat.format_hint,
+        encoding: chat.encoding.clone(),
+    })
+}
+
+/// Saves a parsed chat so that it can be loaded with `read_chat_cache` instead of being parsed again
+/// # Parameters
+/// * `directory` - Directory to save the cache in
+/// * `chat` - Chat that was parsed
+/// * `parsed` - Results of parsing the chat
+fn write_chat_cache(
+    directory: &Path,
+    chat: &ChatToLoad,
+    parsed: &ParsedWhatsAppChat,
+) -> Result<(), String> {
+    let key = chat_cache_key(chat).ok_or("Failed to read chat file".to_owned())?;
+    create_dir_all(directory).map_err(|e| e.to_string())?;
+    let path = directory.join(format!("{0}.bin", chat.id));
+    let temp_path = directory.join(format!("{0}.bin.tmp", chat.id));
+    let mut writer = BufWriter::new(File::create(&temp_path).map_err(|e| e.to_string())?);
+    let cache = ChatCache {
+        messages: parsed.chat.messages.clone(),
+        source_lines: parsed
+            .chat
+            .messages
+            .iter()
+            .map(|m| m.source_lines.clone())
+            .collect(),
+        timestamp_precision: parsed.chat.timestamp_precision,
+        timestamp_format: parsed.chat.timestamp_format.clone(),
+        warnings: parsed.warnings.clone(),
+    };
+    bincode::serialize_into(&mut writer, &key)
+        .and_then(|_| bincode::serialize_into(&mut writer, &cache))
+        .map_err(|e| e.to_string())?;
+    writer.flush().map_err(|e| e.to_string())?;
+    drop(writer);
+    fs::rename(&temp_path, path).map_err(|e| e.to_string())
+}
+
+/// Deletes the cached parse of the chat with the given ID, if there is one
+/// # Parameters
+/// * `directory` - Directory the cache was saved in
+/// * `id` - ID of the chat
+fn remove_chat_cache(directory: &Path, id: Uuid) {
+    let _ = fs::remove_file(directory.join(format!("{0}.bin", id)));
+}
+
+/// Loads a chat saved by `write_chat_cache`, if it's still valid
+///
+/// The cache is only used if the chat file and the settings it was parsed with haven't changed (see `ChatCacheKey`).
+/// Media that can no longer be found is marked as unresolved, and the starred messages are taken from `chat`.
+/// # Parameters
+/// * `directory` - Directory the cache was saved in
+/// * `chat` - Chat to load
+fn read_chat_cache(directory: &Path, chat: &ChatToLoad) -> Option<ParsedWhatsAppChat> {
+    let key = chat_cache_key(chat)?;
+    let file = File::open(directory.join(format!("{0}.bin", chat.id))).ok()?;
+    let mut reader = BufReader::new(file);
+    let cached_key: ChatCacheKey = bincode::deserialize_from(&mut reader).ok()?;
+    if cached_key != key {
+        return None;
+    }
+    let cache: ChatCache = bincode::deserialize_from(&mut reader).ok()?;
+    let mut messages = cache.messages;
+    let mut warnings = cache.warnings;
+    for (m, source_lines) in messages.iter_mut().zip(cache.source_lines) {
+        m.source_lines = source_lines;
+        m.starred.store(false, Relaxed);
+    }
+    for idx in chat.starred.iter() {
+        if let Some(m) = messages.get(*idx) {
+            m.starred.store(true, Relaxed);
+        }
+    }
+    let mut parsed = WhatsAppChat::from_parsed(
+        chat,
+        messages,
+        cache.timestamp_precision,
+        cache.timestamp_format,
+    );
+    let missing = parsed.missing_media();
+    if !missing.is_empty() {
+        parsed = parsed.without_media_paths(&missing);
+        warnings.push(format!(
+            "{0} media files could no longer be found and have been marked as unresolved.",
+            missing.len()
+        ));
+    }
+    Some(ParsedWhatsAppChat {
+        warnings,
+        chat: parsed,
+    })
+}
+
+/// Upgrades saved chat information from an older schema version
+///
+/// Fields added since the data was saved have already been given their defaults when deserializing.
+/// Returns whether anything was changed.
+fn migrate_saved_chats(saved: &mut SavedChats) -> bool {
+    if saved.version >= SAVE_VERSION {
+        return false;
+    }
+    // Version 0 -> 1: only the version field was added
+    // Version 1 -> 2: `you` became a list of aliases, which `deserialize_you` already handles
+    saved.version = SAVE_VERSION;
+    true
+}
+
+/// Gets information about the saved chats
+#[tauri::command]
+fn get_saved_chats(handle: AppHandle) -> Result<SavedChats, String> {
+    let app_data_dir = handle
+        .path()
+        .app_local_data_dir()
+        .map_err(|err| err.to_string())?;
+    let data = fs::read_to_string(app_data_dir.join(SAVE_NAME)).map_err(|e| e.to_string())?;
+    let mut data: SavedChats = match serde_json::from_str(&data) {
+        Ok(d) => d,
+        // Keep the unreadable file around rather than overwriting it on the next save
+        Err(parse_err) => {
+            fs::rename(
+                app_data_dir.join(SAVE_NAME),
+                app_data_dir.join(CORRUPT_SAVE_NAME),
+            )
+            .map_err(|e| e.to_string())?;
+            return Ok(SavedChats {
+                version: SAVE_VERSION,
+                theme: Theme::UNSPECIFIED,
+                chats: Vec::new(),
+                recent: Vec::new(),
+                warnings: vec![format!(
+                    "Saved chats could not be read ({0}); they have been backed up to {1}",
+                    parse_err, CORRUPT_SAVE_NAME
+                )],
+            });
+        }
+    };
+    if migrate_saved_chats(&mut data) {
+        let _ = write_saved_chats(&app_data_dir, &data);
+    }
+    Ok(data)
+}
+
+/// Removes the specified chat, along with its cached parse (see `write_chat_cache`)
+/// # Parameters
+/// * `chat` - Name of the chat to remove
+#[tauri::command]
+fn remove_chat(chat: String, state: State<'_, AppState>, handle: AppHandle) -> Result<(), String> {
+    let mut to_change = state.chats.lock().or(Err("Failed to get lock on state"))?;
+    let cache_dir = handle
+        .path()
+        .app_local_data_dir()
+        .ok()
+        .map(|d| d.join(CHAT_CACHE_DIRECTORY));
+    remove_loaded_chat(&mut to_change, &chat, cache_dir.as_deref());
+    Ok(())
+}
+
+/// Removes the chat named `chat` from `chats`, along with its cached parse
+/// # Parameters
+/// * `chats` - Loaded chats
+/// * `chat` - Name of the chat to remove
+/// * `cache_dir` - Directory the chat's cache was saved in, if known
+fn remove_loaded_chat(chats: &mut Vec<Arc<WhatsAppChat>>, chat: &str, cache_dir: Option<&Path>) {
+    if let Some(cache_dir) = cache_dir {
+        for c in chats.iter().filter(|c| c.name == chat) {
+            remove_chat_cache(cache_dir, c.id);
+        }
+    }
+    chats.retain(|c| c.name != chat);
+}
+
+/// Gets the specified chat
+///
+/// Chats can be very large, so by default only information about the chat is returned; use `get_messages_page` to get
+/// the messages themselves. The chat is recorded as recently viewed.
+/// # Parameters
+/// * `chat` - Name of the chat
+/// * `includeMessages` - Whether to return the whole chat, including its messages
+/// * `redactNumbers` - Whether to mask senders that are phone numbers (see `redact_phone_number`); defaults to `false`
+#[tauri::command]
+#[allow(non_snake_case)]
+fn get_chat(
+    chat: String,
+    includeMessages: Option<bool>,
+    redactNumbers: Option<bool>,
+    state: State<'_, AppState>,
+    handle: AppHandle,
+) -> Result<ChatPayload, String> {
+    let found = state
         .chats
         .lock()
-        .or(Err("Failed to get lock on state".to_owned()))?;
-    let lower_search = search.to_lowercase();
-    for c in locked_chats.iter() {
-        if c.name == chat {
-            return Ok(c
-                .messages
-                .iter()
-                .filter(|m| match &m.content {
-                    MessageContent::Text(text) => text.to_lowercase().contains(&lower_search),
-                    MessageContent::Media(media) => match &media.caption {
-                        Some(caption) => caption.to_lowercase().contains(&lower_search),
-                        _ => false,
-                    },
-                    MessageContent::System(system) => system.to_lowercase().contains(&lower_search),
-                })
-                .map(|m| m.idx)
-                .collect());
+        .or(Err("Failed to get lock on state".to_owned()))?
+        .iter()
+        .find(|c| c.name == chat)
+        .map(Arc::clone);
+    // This writes to disk, so it's done without holding the lock
+    if let (Some(c), Ok(app_data_dir)) = (&found, handle.path().app_local_data_dir()) {
+        let _ = record_recent_chat(&app_data_dir, c.id);
+    }
+
+    match &found {
+        Some(c) if includeMessages.unwrap_or(false) => {
+            if redactNumbers.unwrap_or(false) {
+                return Ok(ChatPayload::Full(Arc::new(c.with_redacted_numbers())));
+            }
+            return Ok(ChatPayload::Full(Arc::clone(c)));
+        }
+        Some(c) => {
+            let redact = |s: String| {
+                if redactNumbers.unwrap_or(false) {
+                    redact_phone_number(&s)
+                } else {
+                    s
+                }
+            };
+            return Ok(ChatPayload::Metadata(ChatMetadata {
+                id: c.id,
+                name: c.name.clone(),
+                file: c.file.clone(),
+                directories: c.directories.clone(),
+                you: c
+                    .you
+                    .lock()
+                    .map(|y| y.clone())
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(redact)
+                    .collect(),
+                number_of_messages: c.messages.len(),
+                counts: c
+                    .count_by_sender()
+                    .into_iter()
+                    .map(|(sender, count)| (redact(sender), count))
+                    .collect(),
+                tz_offset_minutes: c.tz_offset_minutes,
+                timestamp_precision: c.timestamp_precision,
+                timestamp_format: c.timestamp_format.clone(),
+            }));
+        }
+        None => {
+            return Err("Failed to find chat".to_owned());
         }
     }
-    return Err("Failed to find chat".to_owned());
 }
 
-/// Stars or unstars the specified message
-/// # Args
-/// * `chat` - Name of the chat of interest
-/// * `messageIdx` - Index of the message of interest
+/// The lines of a chat file that a message was parsed from
+#[derive(Serialize)]
+struct MessageSourceLines {
+    /// Line number (counting from 1) of the first line
+    first_line: usize,
+    /// The lines, including any blank lines within a multi-line message
+    lines: Vec<String>,
+}
+
+/// Gets the lines of the chat file the specified message was parsed from, e.g. to check that a multi-line message
+/// was put back together correctly
+/// # Parameters
+/// * `chat` - Name of the chat
+/// * `idx` - Index of the message
 #[tauri::command]
-#[allow(non_snake_case)]
-fn star_message(
+fn get_message_raw_lines(
     chat: String,
-    messageIdx: usize,
+    idx: usize,
+    state: State<'_, AppState>,
+) -> Result<MessageSourceLines, String> {
+    let (file, encoding, source_lines) = {
+        let locked_chats = state
+            .chats
+            .lock()
+            .or(Err("Failed to get lock on state".to_owned()))?;
+        let c = locked_chats
+            .iter()
+            .find(|c| c.name == chat)
+            .ok_or("Failed to find chat".to_owned())?;
+        let m = c
+            .messages
+            .get(idx)
+            .ok_or("Failed to find message".to_owned())?;
+        (c.file.clone(), c.encoding.clone(), m.source_lines.clone())
+    };
+    let reader = open_chat_file(&file, encoding.as_deref())?.reader;
+    let lines = reader
+        .split(b'\n')
+        .skip(source_lines.start.saturating_sub(1))
+        .take(source_lines.len())
+        .map(|l| {
+            l.map(|l| {
+                String::from_utf8_lossy(&l)
+                    .trim_end_matches('\r')
+                    .to_owned()
+            })
+            .map_err(|e| e.to_string())
+        })
+        .collect::<Result<Vec<String>, String>>()?;
+    Ok(MessageSourceLines {
+        first_line: source_lines.start,
+        lines,
+    })
+}
+
+/// Gets the IDs of the most recently viewed chats, most recent first
+#[tauri::command]
+fn get_recent_chats(handle: AppHandle) -> Result<Vec<Uuid>, String> {
+    let app_data_dir = handle
+        .path()
+        .app_local_data_dir()
+        .map_err(|err| err.to_string())?;
+    Ok(read_saved_chats(&app_data_dir)
+        .map(|s| s.recent)
+        .unwrap_or_default())
+}
+
+/// Gets a range of the messages of the specified chat
+///
+/// Getting the first page counts as viewing the chat, so it's recorded as recently viewed.
+/// # Parameters
+/// * `chat` - Name of the chat
+/// * `offset` - Index of the first message to get
+/// * `limit` - Maximum number of messages to get; all of the remaining messages are returned if not provided
+/// * `redact_numbers` - Whether to mask senders that are phone numbers (see `redact_phone_number`); defaults to
+///   `false`
+#[tauri::command]
+fn get_messages_page(
+    chat: String,
+    offset: usize,
+    limit: Option<usize>,
+    redact_numbers: Option<bool>,
     state: State<'_, AppState>,
     handle: AppHandle,
-) -> Result<(), String> {
+) -> Result<Vec<Message>, String> {
+    let (id, messages) = {
+        let locked_chats = state
+            .chats
+            .lock()
+            .or(Err("Failed to get lock on state".to_owned()))?;
+        let c = locked_chats
+            .iter()
+            .find(|c| c.name == chat)
+            .ok_or("Failed to find chat".to_owned())?;
+        let messages: Vec<Message> = c
+            .messages
+            .iter()
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|m| {
+                if redact_numbers.unwrap_or(false) {
+                    m.with_redacted_sender()
+                } else {
+                    m.clone()
+                }
+            })
+            .collect();
+        (c.id, messages)
+    };
+    // This writes to disk, so it's done without holding the lock
+    if offset == 0 {
+        if let Ok(app_data_dir) = handle.path().app_local_data_dir() {
+            let _ = record_recent_chat(&app_data_dir, id);
+        }
+    }
+    Ok(messages)
+}
+
+/// Gets the loaded chats in the specified order
+/// # Args
+/// * `order` - How to order the chats
+/// * `reverse` - Whether to reverse the order (e.g. least recently active first); defaults to `false`
+#[tauri::command]
+fn get_chats_sorted(
+    order: ChatOrder,
+    reverse: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<Vec<ChatListEntry>, String> {
+    let locked_chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    let mut entries: Vec<ChatListEntry> = locked_chats
+        .iter()
+        .map(|c| ChatListEntry {
+            id: c.id,
+            name: c.name.clone(),
+            last_sent: c.messages.iter().map(|m| m.timestamp).max(),
+            number_of_messages: c.messages.len(),
+        })
+        .collect();
+    match order {
+        ChatOrder::LAST_SENT => entries.sort_by_key(|e| Reverse(e.last_sent)),
+        ChatOrder::MESSAGE_COUNT => entries.sort_by_key(|e| Reverse(e.number_of_messages)),
+        ChatOrder::NAME => entries.sort_by_key(|e| e.name.to_lowercase()),
+    }
+    if reverse.unwrap_or(false) {
+        entries.reverse();
+    }
+    Ok(entries)
+}
+
+/// Gets summaries of all the loaded chats, without reloading them
+/// # Args
+/// * `redact_numbers` - Whether to mask senders that are phone numbers (see `redact_phone_number`); defaults to
+///   `false`
+#[tauri::command]
+fn get_all_summaries(
+    redact_numbers: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<Vec<ChatSummary>, String> {
     let locked_chats = state
         .chats
         .lock()
         .or(Err("Failed to get lock on state".to_owned()))?;
+    locked_chats
+        .iter()
+        .map(|c| match c.summary() {
+            Ok(summary) if redact_numbers.unwrap_or(false) => Ok(summary.with_redacted_numbers()),
+            summary => summary,
+        })
+        .collect()
+}
+
+/// Loads chats from the frontend
+///
+/// This runs off the main thread so that it can be cancelled with `cancel_load`, in which case `LOAD_CANCELLED` is
+/// returned and the previously loaded chats are left unchanged.
+/// # Parameters
+/// * `chats` - Chats to load
+/// * `use_cache` - Whether to load chats from (and save them to) a cache of their parsed messages, which is much faster
+///   than parsing large chats; a chat is parsed again if its file or settings have changed. Defaults to `false`
+#[tauri::command(async)]
+fn load_chats(
+    chats: Vec<ChatToLoad>,
+    use_cache: Option<bool>,
+    state: State<'_, AppState>,
+    handle: AppHandle,
+) -> Result<Vec<ChatSummary>, String> {
+    let mut names = HashSet::with_capacity(chats.len());
+    for c in chats.iter() {
+        if !names.insert(&c.name) {
+            return Err(format!("Chat name {0} used more than once", c.name));
+        }
+    }
+    state.cancel_load.store(false, Relaxed);
+    let app_data_dir = handle
+        .path()
+        .app_local_data_dir()
+        .map_err(|err| err.to_string())?;
+    let cache_dir = app_data_dir.join(CHAT_CACHE_DIRECTORY);
+    let use_cache = use_cache.unwrap_or(false);
+    // The chats are parsed without holding the lock, so other commands aren't blocked meanwhile
+    let loaded = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state"))?
+        .clone();
+    let mut chat_summaries = Vec::new();
+    let mut parsed_chats = Vec::with_capacity(chats.len());
+    // Indices (in `parsed_chats`) of the chats that were already loaded, along with them as they were then
+    let mut carried_over = Vec::new();
+    for c in chats {
+        if let Some(matching) = loaded.iter().find(|cc| cc.id == c.id) {
+            carried_over.push((parsed_chats.len(), Arc::clone(matching)));
+            // Media may have been moved since the chat was loaded
+            let missing = matching.missing_media();
+            let matching = if missing.is_empty() {
+                Arc::clone(matching)
+            } else {
+                Arc::new(matching.without_media_paths(&missing))
+            };
+            let mut warnings = Vec::new();
+            if !missing.is_empty() {
+                warnings.push(format!(
+                    "{0} media files could no longer be found and have been marked as unresolved.",
+                    missing.len()
+                ));
+            }
+            chat_summaries.push(ChatSummary {
+                name: c.name,
+                warnings,
+                ..matching.summary()?
+            });
+            parsed_chats.push(matching);
+        } else {
+            let cached = if use_cache {
+                read_chat_cache(&cache_dir, &c)
+            } else {
+                None
+            };
+            let p = match cached {
+                Some(p) => p,
+                None => {
+                    let mut p = parse_whatsapp_export(
+                        &c,
+                        |bytes_read, total_bytes| {
+                            let _ = handle.emit(
+                                "load_progress",
+                                LoadProgress {
+                                    chat: c.name.clone(),
+                                    bytes_read,
+                                    total_bytes,
+                                },
+                            );
+                        },
+                        &state.cancel_load,
+                    )?;
+                    if use_cache {
+                        if let Err(e) = write_chat_cache(&cache_dir, &c, &p) {
+                            p.warnings
+                                .push(format!("The parsed chat couldn't be cached: {0}", e));
+                        }
+                    }
+                    p
+                }
+            };
+            let (media_resolved, media_unresolved, media_omitted) = p.chat.count_media_resolution();
+            chat_summaries.push(ChatSummary {
+                warnings: p.warnings,
+                id: c.id,
+                name: c.name,
+                first_sent: p.chat.messages.iter().map(|m| m.timestamp).min(),
+                last_sent: p.chat.messages.iter().map(|m| m.timestamp).max(),
+                last_message: p.chat.messages.last().cloned(),
+                number_of_messages: p.chat.messages.len(),
+                media_resolved,
+                media_unresolved,
+                media_omitted,
+                starred: Vec::new(),
+                you: c.you,
+                tz_offset_minutes: c.tz_offset_minutes,
+                last_read_idx: c.last_read_idx,
+            });
+            parsed_chats.push(Arc::new(p.chat));
+        }
+    }
+    let mut to_change = state.chats.lock().or(Err("Failed to get lock on state"))?;
+    // Chats that were already loaded may have been replaced (such as by `reparse_chat`) while the others were parsed
+    for (i, previous) in carried_over {
+        let Some(current) = to_change.iter().find(|c| c.id == previous.id) else {
+            continue;
+        };
+        if !Arc::ptr_eq(current, &previous) {
+            chat_summaries[i] = ChatSummary {
+                name: chat_summaries[i].name.clone(),
+                warnings: std::mem::take(&mut chat_summaries[i].warnings),
+                ..current.summary()?
+            };
+            parsed_chats[i] = Arc::clone(current);
+        }
+    }
     let theme = state
         .theme
         .lock()
         .or(Err("Failed to get lock on state".to_owned()))?;
-    for c in locked_chats.iter() {
+    let _ = save_basic_chat_data(&app_data_dir, &parsed_chats, *theme);
+    *to_change = parsed_chats;
+    return Ok(chat_summaries);
+}
+
+/// Cancels loading the chats currently being loaded (or parsing a chat again with `reparse_chat`), if any
+#[tauri::command]
+fn cancel_load(state: State<'_, AppState>) {
+    state.cancel_load.store(true, Relaxed);
+    state.cancel_reparse.store(true, Relaxed);
+}
+
+/// Parses the file of the specified chat again (such as after a parsing fix), keeping its settings and starred messages
+///
+/// Starred messages are matched to the newly parsed messages by content, so they're kept even if messages have moved.
+/// Like `load_chats`, this can be cancelled with `cancel_load`. Returns the chat's new summary, including any warnings.
+/// # Parameters
+/// * `chat` - Name of the chat
+#[tauri::command(async)]
+fn reparse_chat(
+    chat: String,
+    state: State<'_, AppState>,
+    handle: AppHandle,
+) -> Result<ChatSummary, String> {
+    state.cancel_reparse.store(false, Relaxed);
+    let old_chat = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?
+        .iter()
+        .find(|c| c.name == chat)
+        .map(Arc::clone)
+        .ok_or("Failed to find chat".to_owned())?;
+    let to_load = ChatToLoad {
+        id: old_chat.id,
+        file: old_chat.file.clone(),
+        directory: old_chat.directories.first().cloned(),
+        name: old_chat.name.clone(),
+        // Starred messages are matched up afterwards, since their indices may have changed
+        starred: Vec::new(),
+        you: old_chat.you.lock().map(|y| y.clone()).unwrap_or_default(),
+        tz_offset_minutes: old_chat.tz_offset_minutes,
+        sender_colors: old_chat
+            .sender_colors
+            .lock()
+            .map(|c| c.clone())
+            .unwrap_or_default(),
+        merge_sender_case: old_chat.merge_sender_case,
+        automated_patterns: old_chat.automated_patterns.clone(),
+        format_hint: old_chat.format_hint,
+        encoding: old_chat.encoding.clone(),
+        last_read_idx: old_chat.last_read_idx.lock().ok().and_then(|l| *l),
+        gap_threshold_minutes: old_chat.gap_threshold_minutes.lock().ok().and_then(|g| *g),
+        // The file was loaded before, so it's known to be a chat
+        max_file_size: Some(u64::MAX),
+    };
+    // The chat is parsed without holding the lock, so other commands aren't blocked meanwhile
+    let p = parse_whatsapp_export(
+        &to_load,
+        |bytes_read, total_bytes| {
+            let _ = handle.emit(
+                "load_progress",
+                LoadProgress {
+                    chat: to_load.name.clone(),
+                    bytes_read,
+                    total_bytes,
+                },
+            );
+        },
+        &state.cancel_reparse,
+    )?;
+    let mut warnings = p.warnings;
+    let mut new_chat = p.chat;
+    let mut chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    let chat_idx = chats
+        .iter()
+        .position(|c| c.id == old_chat.id)
+        .ok_or("Failed to find chat".to_owned())?;
+    // The chat may have changed while it was being parsed, so its stars and settings are taken from it as it is now
+    let old_chat = Arc::clone(&chats[chat_idx]);
+    new_chat.you = Arc::clone(&old_chat.you);
+    new_chat.sender_colors = Arc::clone(&old_chat.sender_colors);
+    new_chat.last_read_idx = Arc::clone(&old_chat.last_read_idx);
+    new_chat.gap_threshold_minutes = Arc::clone(&old_chat.gap_threshold_minutes);
+    let mut unmatched_stars = 0;
+    for old in old_chat.messages.iter().filter(|m| m.starred.load(Relaxed)) {
+        let matching = new_chat
+            .messages
+            .get(old.idx)
+            .filter(|m| *m == old)
+            .or_else(|| new_chat.messages.iter().find(|m| *m == old));
+        match matching {
+            Some(m) => m.starred.store(true, Relaxed),
+            None => unmatched_stars += 1,
+        }
+    }
+    if unmatched_stars > 0 {
+        warnings.push(format!(
+            "{0} starred messages could no longer be found and have been unstarred.",
+            unmatched_stars
+        ));
+    }
+    let summary = ChatSummary {
+        warnings,
+        ..new_chat.summary()?
+    };
+    chats[chat_idx] = Arc::new(new_chat);
+    let theme = *state
+        .theme
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    let app_data_dir = handle
+        .path()
+        .app_local_data_dir()
+        .map_err(|err| err.to_string())?;
+    save_basic_chat_data(&app_data_dir, &chats, theme)?;
+    Ok(summary)
+}
+
+/// Sets the "you" of the specified chat, replacing any previous aliases
+/// # Parameters
+/// * `chat` - Name of the chat
+/// * `you` - Names of the senders who are "you"
+#[tauri::command]
+fn set_you(
+    chat: String,
+    you: Vec<String>,
+    state: State<'_, AppState>,
+    handle: AppHandle,
+) -> Result<(), String> {
+    let chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in chats.iter() {
         if c.name == chat {
-            if messageIdx >= c.messages.len() {
-                return Err("No message exists at that index".to_owned());
+            // This needs to be in a separate closure to prevent `save_basic_chat_data` from deadlocking
+            {
+                let mut chat_you = c
+                    .you
+                    .lock()
+                    .or(Err("Failed to get lock on state".to_owned()))?;
+                *chat_you = you;
             }
-            c.messages[messageIdx].starred.fetch_not(Relaxed);
+            let theme = *state
+                .theme
+                .lock()
+                .or(Err("Failed to get lock on state".to_owned()))?;
             let app_data_dir = handle
                 .path()
                 .app_local_data_dir()
                 .map_err(|err| err.to_string())?;
-            let _ = save_basic_chat_data(&app_data_dir, &locked_chats, *theme);
-            return Ok(());
+            return save_basic_chat_data(&app_data_dir, &chats, theme);
         }
     }
-    return Err("Failed to find chat".to_owned());
+    Err("Failed to find chat".to_owned())
 }
 
-/// Gets the starred messages for the specified `chat`
-/// # Args
-/// * `chat` - Name of the chat
+/// Checks whether the specified chats (such as several exports of the same conversation) agree on who is "you"
+///
+/// Chats without any "you" set are ignored. If the chats disagree, the aliases of each chat are returned, and if
+/// `canonical_you` is given, it's set as the "you" of all the chats. If they agree, nothing is returned.
+/// # Parameters
+/// * `chats` - Names of the chats
+/// * `canonical_you` - Names of the senders who should be "you" in all the chats if they disagree
 #[tauri::command]
-fn get_starred(chat: String, state: State<'_, AppState>) -> Result<Vec<Message>, String> {
+fn check_you_consistency(
+    chats: Vec<String>,
+    canonical_you: Option<Vec<String>>,
+    state: State<'_, AppState>,
+    handle: AppHandle,
+) -> Result<Vec<ChatYou>, String> {
     let locked_chats = state
         .chats
         .lock()
         .or(Err("Failed to get lock on state".to_owned()))?;
-    for c in locked_chats.iter() {
-        if c.name == chat {
-            return Ok(c
-                .messages
-                .iter()
-                .filter(|m| m.starred.load(Relaxed))
-                .map(|m| m.clone())
-                .collect());
+    let mut aliases = Vec::with_capacity(chats.len());
+    for name in chats.iter() {
+        let c = locked_chats
+            .iter()
+            .find(|c| &c.name == name)
+            .ok_or(format!("Failed to find chat {0}", name))?;
+        let you = c
+            .you
+            .lock()
+            .or(Err("Failed to get lock on state".to_owned()))?
+            .clone();
+        aliases.push(ChatYou {
+            name: name.clone(),
+            you,
+        });
+    }
+    let distinct: HashSet<Vec<&String>> = aliases
+        .iter()
+        .filter(|a| !a.you.is_empty())
+        .map(|a| {
+            let mut you: Vec<&String> = a.you.iter().collect();
+            you.sort();
+            you.dedup();
+            you
+        })
+        .collect();
+    if distinct.len() <= 1 {
+        return Ok(Vec::new());
+    }
+    if let Some(canonical_you) = canonical_you {
+        for c in locked_chats.iter().filter(|c| chats.contains(&c.name)) {
+            *c.you
+                .lock()
+                .or(Err("Failed to get lock on state".to_owned()))? = canonical_you.clone();
         }
+        let theme = *state
+            .theme
+            .lock()
+            .or(Err("Failed to get lock on state".to_owned()))?;
+        let app_data_dir = handle
+            .path()
+            .app_local_data_dir()
+            .map_err(|err| err.to_string())?;
+        save_basic_chat_data(&app_data_dir, &locked_chats, theme)?;
     }
-    return Err("Failed to find chat".to_owned());
+    Ok(aliases)
 }
 
-/// Gets chat statistics
+/// Sets the patterns identifying automated messages in the specified chat, and re-tags its messages
+///
+/// Returns the number of messages that are now tagged as automated.
+/// # Parameters
+/// * `chat` - Name of the chat
+/// * `patterns` - Text contained in automated messages (such as part of a business's greeting), ignoring case
 #[tauri::command]
-fn get_stats(
+fn set_automated_patterns(
     chat: String,
+    patterns: Vec<String>,
     state: State<'_, AppState>,
-) -> Result<HashMap<String, MessageTypeCount>, String> {
-    let locked_chats = state
+    handle: AppHandle,
+) -> Result<usize, String> {
+    let mut chats = state
         .chats
         .lock()
         .or(Err("Failed to get lock on state".to_owned()))?;
-    for c in locked_chats.iter() {
-        if c.name == chat {
-            return Ok(c.count_by_sender());
-        }
-    }
-    Err("Failed to find chat".to_owned())
-}
-
-/// Searches `directory` for a file named `path`; if one is found, the full string path
-fn full_file_path(
-    path: &str,
-    directory: &Option<String>,
-    directory_files: &HashSet<String>,
-) -> Option<String> {
-    match directory {
-        Some(dir) if directory_files.contains(path) => {
-            Some(Path::new(dir).join(path).to_string_lossy().into_owned())
+    let chat_idx = chats
+        .iter()
+        .position(|c| c.name == chat)
+        .ok_or("Failed to find chat".to_owned())?;
+    let mut new_chat = (*chats[chat_idx]).clone();
+    let mut automated = 0;
+    for m in new_chat.messages.iter_mut() {
+        m.automated = is_automated(&m.content, &patterns);
+        if m.automated {
+            automated += 1;
         }
-        _ => None,
     }
+    new_chat.automated_patterns = patterns;
+    new_chat.bump_revision();
+    chats[chat_idx] = Arc::new(new_chat);
+    let theme = *state
+        .theme
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    let app_data_dir = handle
+        .path()
+        .app_local_data_dir()
+        .map_err(|err| err.to_string())?;
+    save_basic_chat_data(&app_data_dir, &chats, theme)?;
+    Ok(automated)
 }
 
-/// Parses a WhatsApp chat export
+/// Sets the media directory of the specified chat and re-resolves the paths of its media
+///
+/// Returns the number of media messages that weren't previously found but now are.
 /// # Parameters
-/// * `path` - Path to the chat file
-fn parse_whatsapp_export(
-    path: &str,
-    directory: &Option<String>,
-    name: &str,
-    id: &Uuid,
-    starred: &Vec<usize>,
-    you: &Option<String>,
-) -> Result<ParsedWhatsAppChat, String> {
-    let file = File::open(path).or(Err("Error opening file"))?;
-    let reader: BufReader<File> = BufReader::new(file);
-    let mut first = true;
-    let mut version = ExportVersion::NEW;
-    let mut messages: Vec<Message> = Vec::new();
-    let mut senders: HashSet<String> = HashSet::with_capacity(2);
-    let mut directory_files = HashSet::new();
-    let mut warnings = Vec::new();
-    match directory {
-        Some(dir) => match fs::read_dir(dir) {
-            Ok(paths) => {
-                paths.for_each(|p| match p {
-                    Ok(dir_entry) => {
-                        let dir_entry_path = dir_entry.path();
-                        if dir_entry_path.is_file() {
-                            match dir_entry_path.file_name() {
-                                Some(file_name) => {
-                                    directory_files
-                                        .insert(file_name.to_string_lossy().into_owned());
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                    _ => {}
-                });
-            }
-            _ => {}
-        },
-        _ => {}
-    }
-    for line in reader.lines() {
-        match line {
-            Ok(l) => {
-                let l = l.trim().replace('\u{200e}', "");
-                if l.trim().len() == 0 {
-                    continue;
-                }
-                if first {
-                    if l.chars().next().unwrap_or(' ') == '[' {
-                        version = ExportVersion::OLD;
-                    }
-                    first = false;
-                }
-                match version {
-                    ExportVersion::OLD => {
-                        // If the message doesn't start with a open square bracket, it's a continuation of the previous message
-                        if !l.starts_with('[') {
-                            if let Some(last_idx) = messages.len().checked_sub(1) {
-                                let last_msg = &messages[last_idx];
-                                if let MessageContent::Text(last_msg_content) = &last_msg.content {
-                                    messages[last_idx] = Message {
-                                        timestamp: last_msg.timestamp,
-                                        sender: last_msg.sender.clone(),
-                                        content: MessageContent::Text(
-                                            last_msg_content.to_owned() + "\n" + &l,
-                                        ),
-                                        starred: AtomicBool::new(false),
-                                        idx: last_msg.idx,
-                                    };
-                                }
-                            }
-                        }
-                        // Otherwise it's the start of a normal message
-                        else {
-                            // Get the end time
-                            let time_end_idx = l.find("] ").ok_or("Failed to find time end")?;
-                            let timestamp = NaiveDateTime::parse_from_str(
-                                &l[1..time_end_idx],
-                                "%m/%d/%y, %I:%M:%S %p",
-                            )
-                            .or(Err(format!(
-                                "Failed to parse time: {0}",
-                                &l[1..time_end_idx]
-                            )))?;
-                            if let Some(col_i) = l[time_end_idx + 2..].find(": ") {
-                                let colon_idx = col_i + time_end_idx + 2;
-                                let sender = l[time_end_idx + 2..colon_idx].to_string();
-                                senders.insert(sender.clone());
-                                if l.contains("<attached: ") {
-                                    let attached_idx = l.find("<attached: ").unwrap();
-                                    let file_name = &l[attached_idx + 11..l.len() - 1];
-                                    let media_type = if PHOTO_TYPES
-                                        .iter()
-                                        .any(|ext| file_name.to_lowercase().ends_with(ext))
-                                    {
-                                        MediaType::PHOTO
-                                    } else if VIDEO_TYPES
-                                        .iter()
-                                        .any(|ext| file_name.to_lowercase().ends_with(ext))
-                                    {
-                                        MediaType::VIDEO
-                                    } else if AUDIO_TYPES
-                                        .iter()
-                                        .any(|ext| file_name.to_lowercase().ends_with(ext))
-                                    {
-                                        MediaType::AUDIO
-                                    } else {
-                                        MediaType::OTHER
-                                    };
-                                    messages.push(Message {
-                                        timestamp,
-                                        sender: Some(sender),
-                                        content: MessageContent::Media(Media {
-                                            media_type,
-                                            path: full_file_path(
-                                                file_name,
-                                                directory,
-                                                &directory_files,
-                                            ),
-                                            caption: None,
-                                        }),
-                                        starred: AtomicBool::new(false),
-                                        idx: messages.len(),
-                                    });
-                                } else {
-                                    messages.push(Message {
-                                        timestamp,
-                                        sender: Some(sender),
-                                        content: MessageContent::Text(
-                                            l[colon_idx + 2..].to_string(),
-                                        ),
-                                        starred: AtomicBool::new(false),
-                                        idx: messages.len(),
-                                    });
-                                }
-                            }
-                            // Handle "system" messages
-                            else {
-                                // Icon messages aren't included in the "new" exports, which can hinder matching them up
-                                if !l[time_end_idx + 2..].ends_with("icon") {
-                                    // They probably start with a previous user's name
-                                    let mut sender = None;
-                                    for s in senders.iter() {
-                                        if l[time_end_idx + 2..].starts_with(s) {
-                                            sender = Some(s.to_owned());
-                                            break;
-                                        }
-                                    }
-                                    messages.push(Message {
-                                        timestamp,
-                                        sender,
-                                        content: MessageContent::System(
-                                            l[time_end_idx + 2..].to_string(),
-                                        ),
-                                        starred: AtomicBool::new(false),
-                                        idx: messages.len(),
-                                    });
-                                }
-                            }
-                        }
-                    }
-                    ExportVersion::NEW => {
-                        // Find the index of (A/P)M - <name>
-                        if let Some(dash_idx) = l.find("M - ") {
-                            if dash_idx <= 19 {
-                                let timestamp = NaiveDateTime::parse_from_str(
-                                    &l[..dash_idx + 1],
-                                    "%m/%d/%y, %I:%M %p",
-                                )
-                                .or(Err(format!(
-                                    "Failed to parse time: {0}",
-                                    &l[..dash_idx + 1]
-                                )))?;
-                                if let Some(col_i) = l[dash_idx + 4..].find(": ") {
-                                    let colon_idx = col_i + dash_idx + 4;
-                                    let sender = l[dash_idx + 4..colon_idx].to_string();
-                                    senders.insert(sender.clone());
-                                    if l.contains("<Media omitted") {
-                                        messages.push(Message {
-                                            timestamp,
-                                            sender: Some(sender),
-                                            content: MessageContent::Media(Media {
-                                                media_type: MediaType::OTHER,
-                                                path: None,
-                                                caption: None,
-                                            }),
-                                            starred: AtomicBool::new(false),
-                                            idx: messages.len(),
-                                        });
-                                    } else if l.ends_with("(file attached)") {
-                                        let file_name = &l[colon_idx + 2..l.len() - 16];
-                                        let media_type = if PHOTO_TYPES
-                                            .iter()
-                                            .any(|ext| file_name.to_lowercase().ends_with(ext))
-                                        {
-                                            MediaType::PHOTO
-                                        } else if VIDEO_TYPES
-                                            .iter()
-                                            .any(|ext| file_name.to_lowercase().ends_with(ext))
-                                        {
-                                            MediaType::VIDEO
-                                        } else if AUDIO_TYPES
-                                            .iter()
-                                            .any(|ext| file_name.to_lowercase().ends_with(ext))
-                                        {
-                                            MediaType::AUDIO
-                                        } else {
-                                            MediaType::OTHER
-                                        };
-                                        messages.push(Message {
-                                            timestamp,
-                                            sender: Some(sender),
-                                            content: MessageContent::Media(Media {
-                                                media_type,
-                                                path: full_file_path(
-                                                    file_name,
-                                                    directory,
-                                                    &directory_files,
-                                                ),
-                                                caption: None,
-                                            }),
-                                            starred: AtomicBool::new(false),
-                                            idx: messages.len(),
-                                        });
-                                    } else if l[colon_idx + 2..].to_string().trim() != "null" {
-                                        messages.push(Message {
-                                            timestamp,
-                                            sender: Some(sender),
-                                            content: MessageContent::Text(
-                                                l[colon_idx + 2..].to_string(),
-                                            ),
-                                            starred: AtomicBool::new(false),
-                                            idx: messages.len(),
-                                        });
-                                    }
-                                }
-                                // Handle "system" messages
-                                else {
-                                    // They probably start with a previous user's name
-                                    let mut sender = None;
-                                    for s in senders.iter() {
-                                        if l[dash_idx + 4..].starts_with(s) {
-                                            sender = Some(s.to_owned());
-                                            break;
-                                        }
-                                    }
-                                    messages.push(Message {
-                                        timestamp,
-                                        sender,
-                                        content: MessageContent::System(
-                                            l[dash_idx + 4..].to_string(),
-                                        ),
-                                        starred: AtomicBool::new(false),
-                                        idx: messages.len(),
-                                    });
-                                }
-                            }
-                            // If the dash is not in the first 19 characters, it's not part of the message time
-                            else if let Some(last_idx) = messages.len().checked_sub(1) {
-                                let last_msg = &messages[last_idx];
-                                if let MessageContent::Text(last_msg_content) = &last_msg.content {
-                                    messages[last_idx] = Message {
-                                        timestamp: last_msg.timestamp,
-                                        sender: last_msg.sender.clone(),
-                                        content: MessageContent::Text(
-                                            last_msg_content.to_owned() + "\n" + &l,
-                                        ),
-                                        starred: AtomicBool::new(false),
-                                        idx: last_msg.idx,
-                                    };
-                                }
-                            }
-                        }
-                        // If there is no match, it's probably a continuation of the previous message
-                        else if let Some(last_idx) = messages.len().checked_sub(1) {
-                            let last_msg = &messages[last_idx];
-                            if let MessageContent::Text(last_msg_content) = &last_msg.content {
-                                messages[last_idx] = Message {
-                                    timestamp: last_msg.timestamp,
-                                    sender: last_msg.sender.clone(),
-                                    content: MessageContent::Text(
-                                        last_msg_content.to_owned() + "\n" + &l,
-                                    ),
-                                    starred: AtomicBool::new(false),
-                                    idx: last_msg.idx,
-                                };
-                            } else if let MessageContent::Media(last_msg_content) =
-                                &last_msg.content
-                            {
-                                messages[last_idx] = Message {
-                                    timestamp: last_msg.timestamp,
-                                    sender: last_msg.sender.clone(),
-                                    content: MessageContent::Media(Media {
-                                        media_type: last_msg_content.media_type,
-                                        path: last_msg_content.path.clone(),
-                                        caption: match &last_msg_content.caption {
-                                            Some(old_caption) => {
-                                                Some(old_caption.to_owned() + "\n" + &l)
-                                            }
-                                            None => Some(l),
-                                        },
-                                    }),
-                                    starred: AtomicBool::new(false),
-                                    idx: last_msg.idx,
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-            Err(_) => {}
-        }
-    }
-    let mut new_messages = HashMap::new();
-    for i in 0..messages.len() {
-        match &messages[i].content {
-            MessageContent::System(content) => {
-                if messages[i].sender.is_none() {
-                    for s in senders.iter() {
-                        if content.starts_with(s) {
-                            new_messages.insert(
-                                i,
-                                Message {
-                                    timestamp: messages[i].timestamp,
-                                    sender: Some(s.to_owned()),
-                                    content: messages[i].content.clone(),
-                                    starred: AtomicBool::new(false),
-                                    idx: messages[i].idx,
-                                },
-                            );
-                            break;
-                        }
-                    }
+/// * `chat` - Name of the chat
+/// * `directory` - New media directory, if any
+#[tauri::command]
+fn set_media_directory(
+    chat: String,
+    directory: Option<String>,
+    state: State<'_, AppState>,
+    handle: AppHandle,
+) -> Result<usize, String> {
+    let mut chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    let chat_idx = chats
+        .iter()
+        .position(|c| c.name == chat)
+        .ok_or("Failed to find chat".to_owned())?;
+    let (directory_files, _) = list_directory_files(&directory);
+    let mut new_chat = (*chats[chat_idx]).clone();
+    let mut newly_resolved = 0;
+    for m in new_chat.messages.iter_mut() {
+        if let MessageContent::Media(media) = &mut m.content {
+            if let Some(file_name) = &media.file_name {
+                let path = full_file_path(file_name, &directory_files, &mut Vec::new());
+                if media.path.is_none() && path.is_some() {
+                    newly_resolved += 1;
                 }
+                media.path = path;
             }
-            _ => {}
-        }
-    }
-    for (idx, new_messages) in new_messages {
-        messages[idx] = new_messages;
-    }
-    messages.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
-    for idx in starred {
-        if let Some(m) = messages.get(*idx) {
-            m.starred.store(true, Relaxed);
-        } else {
-            warnings.push(
-                "Some starred indices not found; these messages have not been stared.".to_owned(),
-            );
         }
     }
-    Ok(ParsedWhatsAppChat {
-        warnings,
-        chat: WhatsAppChat {
-            id: *id,
-            messages,
-            file: path.to_owned(),
-            directories: match directory {
-                Some(d) => vec![d.clone()],
-                None => Vec::new(),
-            },
-            name: name.to_owned(),
-            you: Arc::new(Mutex::new(you.clone())),
-        },
-    })
+    new_chat.directories = directory.into_iter().collect();
+    new_chat.bump_revision();
+    chats[chat_idx] = Arc::new(new_chat);
+    let theme = *state
+        .theme
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    let app_data_dir = handle
+        .path()
+        .app_local_data_dir()
+        .map_err(|err| err.to_string())?;
+    save_basic_chat_data(&app_data_dir, &chats, theme)?;
+    Ok(newly_resolved)
 }
 
-/// Gets information about the saved chats
+/// Checks that the media of the specified chat can still be found, marking any that can't as unresolved
+///
+/// Returns the number of media messages that could no longer be found.
+/// # Parameters
+/// * `chat` - Name of the chat
 #[tauri::command]
-fn get_saved_chats(handle: AppHandle) -> Result<SavedChats, String> {
+fn verify_media(
+    chat: String,
+    state: State<'_, AppState>,
+    handle: AppHandle,
+) -> Result<usize, String> {
+    let mut chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    let chat_idx = chats
+        .iter()
+        .position(|c| c.name == chat)
+        .ok_or("Failed to find chat".to_owned())?;
+    let missing = chats[chat_idx].missing_media();
+    if missing.is_empty() {
+        return Ok(0);
+    }
+    chats[chat_idx] = Arc::new(chats[chat_idx].without_media_paths(&missing));
+    let theme = *state
+        .theme
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
     let app_data_dir = handle
         .path()
         .app_local_data_dir()
         .map_err(|err| err.to_string())?;
-    let data = fs::read_to_string(app_data_dir.join(SAVE_NAME)).map_err(|e| e.to_string())?;
-    let data = serde_json::from_str(&data).map_err(|e| e.to_string())?;
-    Ok(data)
+    save_basic_chat_data(&app_data_dir, &chats, theme)?;
+    Ok(missing.len())
 }
 
-/// Removes the specified chat
+/// Gives the specified chat a new unique ID, e.g. to resolve a collision with another chat
+///
+/// Returns the new ID.
 /// # Parameters
-/// * `chat` - Name of the chat to remove
+/// * `chat` - Name of the chat
 #[tauri::command]
-fn remove_chat(chat: String, state: State<'_, AppState>) -> Result<(), String> {
-    let mut to_change = state.chats.lock().or(Err("Failed to get lock on state"))?;
-    *to_change = to_change
+fn regenerate_chat_id(
+    chat: String,
+    state: State<'_, AppState>,
+    handle: AppHandle,
+) -> Result<Uuid, String> {
+    let mut chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    let chat_idx = chats
         .iter()
-        .filter_map(|c| {
-            if c.name == chat {
-                Some(Arc::clone(c))
-            } else {
-                None
-            }
-        })
-        .collect();
-    Ok(())
+        .position(|c| c.name == chat)
+        .ok_or("Failed to find chat".to_owned())?;
+    let mut id = Uuid::new_v4();
+    while chats.iter().any(|c| c.id == id) {
+        id = Uuid::new_v4();
+    }
+    let mut new_chat = (*chats[chat_idx]).clone();
+    let old_id = new_chat.id;
+    new_chat.id = id;
+    chats[chat_idx] = Arc::new(new_chat);
+    let theme = *state
+        .theme
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    let app_data_dir = handle
+        .path()
+        .app_local_data_dir()
+        .map_err(|err| err.to_string())?;
+    // The cache may belong to the chat the ID collided with, so it's no longer trusted
+    remove_chat_cache(&app_data_dir.join(CHAT_CACHE_DIRECTORY), old_id);
+    save_basic_chat_data(&app_data_dir, &chats, theme)?;
+    Ok(id)
 }
 
+/// Sets the display color of a sender in the specified chat
+/// # Parameters
+/// * `chat` - Name of the chat
+/// * `sender` - Name of the sender
+/// * `color` - Color to use, or `None` to remove the override
 #[tauri::command]
-fn get_chat(chat: String, state: State<'_, AppState>) -> Result<Arc<WhatsAppChat>, String> {
-    let locked_chats = state
+fn set_sender_color(
+    chat: String,
+    sender: String,
+    color: Option<String>,
+    state: State<'_, AppState>,
+    handle: AppHandle,
+) -> Result<(), String> {
+    let chats = state
         .chats
         .lock()
         .or(Err("Failed to get lock on state".to_owned()))?;
-
-    match locked_chats.iter().find(|c| c.name == chat) {
-        Some(c) => {
-            return Ok(Arc::clone(c));
-        }
-        None => {
-            return Err("Failed to find chat".to_owned());
+    for c in chats.iter() {
+        if c.name == chat {
+            // This needs to be in a separate closure to prevent `save_basic_chat_data` from deadlocking
+            {
+                let mut sender_colors = c
+                    .sender_colors
+                    .lock()
+                    .or(Err("Failed to get lock on state".to_owned()))?;
+                match color {
+                    Some(color) => sender_colors.insert(sender, color),
+                    None => sender_colors.remove(&sender),
+                };
+            }
+            let theme = *state
+                .theme
+                .lock()
+                .or(Err("Failed to get lock on state".to_owned()))?;
+            let app_data_dir = handle
+                .path()
+                .app_local_data_dir()
+                .map_err(|err| err.to_string())?;
+            return save_basic_chat_data(&app_data_dir, &chats, theme);
         }
     }
+    Err("Failed to find chat".to_owned())
 }
 
-/// Loads chats from the frontend
+/// Sets the gap between messages after which a new conversation starts in the specified chat, which is used by
+/// conversation stats (such as `get_initiative_balance`) by default
+/// # Parameters
+/// * `chat` - Name of the chat
+/// * `minutes` - Gap in minutes; if not given, `DEFAULT_CONVERSATION_GAP_MINUTES` is used
 #[tauri::command]
-fn load_chats(
-    chats: Vec<ChatToLoad>,
+fn set_gap_threshold(
+    chat: String,
+    minutes: Option<i64>,
     state: State<'_, AppState>,
     handle: AppHandle,
-) -> Result<Vec<ChatSummary>, String> {
-    let mut names = HashSet::with_capacity(chats.len());
-    for c in chats.iter() {
-        if !names.insert(&c.name) {
-            return Err(format!("Chat name {0} used more than once", c.name));
-        }
+) -> Result<(), String> {
+    if minutes.is_some_and(|m| m <= 0) {
+        return Err("The gap must be positive".to_owned());
     }
-    let mut to_change = state.chats.lock().or(Err("Failed to get lock on state"))?;
-    let mut chat_summaries = Vec::new();
-    let mut parsed_chats = Vec::with_capacity(chats.len());
-    for c in chats {
-        if let Some(matching) = to_change.iter().find(|cc| cc.id == c.id) {
-            let you = matching.you.lock().or(Err("Failed to get lock on you"))?;
-            parsed_chats.push(Arc::clone(matching));
-            chat_summaries.push(ChatSummary {
-                warnings: Vec::new(),
-                name: c.name,
-                first_sent: matching.messages.iter().map(|m| m.timestamp).min(),
-                last_sent: matching.messages.iter().map(|m| m.timestamp).max(),
-                last_message: matching.messages.last().cloned(),
-                number_of_messages: matching.messages.len(),
-                starred: matching
-                    .messages
-                    .iter()
-                    .filter_map(|m| {
-                        if m.starred.load(Relaxed) {
-                            Some(m.clone())
-                        } else {
-                            None
-                        }
-                    })
-                    .collect(),
-                you: you.clone(),
-            });
-        } else {
-            let p =
-                parse_whatsapp_export(&c.file, &c.directory, &c.name, &c.id, &c.starred, &c.you)?;
-            chat_summaries.push(ChatSummary {
-                warnings: p.warnings,
-                name: c.name,
-                first_sent: p.chat.messages.iter().map(|m| m.timestamp).min(),
-                last_sent: p.chat.messages.iter().map(|m| m.timestamp).max(),
-                last_message: p.chat.messages.last().cloned(),
-                number_of_messages: p.chat.messages.len(),
-                starred: Vec::new(),
-                you: c.you,
-            });
-            parsed_chats.push(Arc::new(p.chat));
+    let chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in chats.iter() {
+        if c.name == chat {
+            // This needs to be in a separate closure to prevent `save_basic_chat_data` from deadlocking
+            {
+                let mut gap_threshold_minutes = c
+                    .gap_threshold_minutes
+                    .lock()
+                    .or(Err("Failed to get lock on state".to_owned()))?;
+                *gap_threshold_minutes = minutes;
+            }
+            let theme = *state
+                .theme
+                .lock()
+                .or(Err("Failed to get lock on state".to_owned()))?;
+            let app_data_dir = handle
+                .path()
+                .app_local_data_dir()
+                .map_err(|err| err.to_string())?;
+            return save_basic_chat_data(&app_data_dir, &chats, theme);
         }
     }
-    let app_data_dir = handle
-        .path()
-        .app_local_data_dir()
-        .map_err(|err| err.to_string())?;
-    let theme = state
-        .theme
+    Err("Failed to find chat".to_owned())
+}
+
+/// Gets the gap (in minutes) between messages after which a new conversation starts in the specified chat
+/// # Parameters
+/// * `chat` - Name of the chat
+#[tauri::command]
+fn get_gap_threshold(chat: String, state: State<'_, AppState>) -> Result<i64, String> {
+    let chats = state
+        .chats
         .lock()
         .or(Err("Failed to get lock on state".to_owned()))?;
-    let _ = save_basic_chat_data(&app_data_dir, &parsed_chats, *theme);
-    *to_change = parsed_chats;
-    return Ok(chat_summaries);
+    for c in chats.iter() {
+        if c.name == chat {
+            return Ok(c.gap_threshold());
+        }
+    }
+    Err("Failed to find chat".to_owned())
 }
 
-/// Sets the "you" of the specified chat
+/// Marks all the messages of the specified chat as read
+///
+/// If the chat has no messages, there's nothing to have read, so the last read message is cleared.
 /// # Parameters
 /// * `chat` - Name of the chat
-/// * `you` - Name of the sender, if any
 #[tauri::command]
-fn set_you(
+fn mark_chat_read(
     chat: String,
-    you: Option<String>,
     state: State<'_, AppState>,
     handle: AppHandle,
 ) -> Result<(), String> {
@@ -1024,11 +7103,11 @@ fn set_you(
         if c.name == chat {
             // This needs to be in a separate closure to prevent `save_basic_chat_data` from deadlocking
             {
-                let mut chat_you = c
-                    .you
+                let mut last_read_idx = c
+                    .last_read_idx
                     .lock()
                     .or(Err("Failed to get lock on state".to_owned()))?;
-                *chat_you = you;
+                *last_read_idx = c.messages.len().checked_sub(1);
             }
             let theme = *state
                 .theme
@@ -1044,6 +7123,30 @@ fn set_you(
     Err("Failed to find chat".to_owned())
 }
 
+/// Gets the display colors chosen for the senders of the specified chat
+/// # Parameters
+/// * `chat` - Name of the chat
+#[tauri::command]
+fn get_sender_colors(
+    chat: String,
+    state: State<'_, AppState>,
+) -> Result<HashMap<String, String>, String> {
+    let chats = state
+        .chats
+        .lock()
+        .or(Err("Failed to get lock on state".to_owned()))?;
+    for c in chats.iter() {
+        if c.name == chat {
+            return Ok(c
+                .sender_colors
+                .lock()
+                .or(Err("Failed to get lock on state".to_owned()))?
+                .clone());
+        }
+    }
+    Err("Failed to find chat".to_owned())
+}
+
 /// Sets the current theme
 /// # Parameters
 /// * `theme` - Theme to use
@@ -1062,16 +7165,16 @@ fn set_theme(theme: Theme, state: State<'_, AppState>, handle: AppHandle) -> Res
         let saved: Result<SavedChats, _> = serde_json::from_str(&data);
         match saved {
             Ok(s) => {
-                let f =
-                    fs::File::create(app_data_dir.join(SAVE_NAME)).map_err(|e| e.to_string())?;
-                return serde_json::to_writer(
-                    f,
+                return write_saved_chats(
+                    &app_data_dir,
                     &SavedChats {
+                        version: SAVE_VERSION,
                         theme,
                         chats: s.chats,
+                        recent: s.recent,
+                        warnings: Vec::new(),
                     },
-                )
-                .map_err(|e| e.to_string());
+                );
             }
             _ => {}
         }
@@ -1120,6 +7223,9 @@ pub fn run() {
         .manage(AppState {
             chats: Vec::new().into(),
             theme: Theme::UNSPECIFIED.into(),
+            search_id: AtomicU64::new(0),
+            cancel_load: AtomicBool::new(false),
+            cancel_reparse: AtomicBool::new(false),
         })
         .invoke_handler(tauri::generate_handler![
             get_set_theme_initial,
@@ -1132,8 +7238,651 @@ pub fn run() {
             search,
             star_message,
             get_starred,
-            get_stats
+            get_stats,
+            get_day_boundaries,
+            find_duplicates,
+            export_search_results,
+            resolve_anchor,
+            get_milestones,
+            find_nth_by,
+            set_sender_color,
+            get_sender_colors,
+            set_media_directory,
+            get_media_by_sender,
+            detect_language,
+            get_adjacent_starred,
+            get_heatmap,
+            regenerate_chat_id,
+            on_this_day,
+            get_burst_stats,
+            search_with_offsets,
+            get_monthly_top_sender,
+            get_sentiment_estimate,
+            get_chat_type,
+            get_links,
+            get_weekday_hour_grid,
+            search_stream,
+            cancel_search,
+            cancel_load,
+            compare_senders,
+            get_chats_sorted,
+            export_stats_markdown,
+            check_you_consistency,
+            get_busiest_day,
+            get_all_summaries,
+            set_automated_patterns,
+            neighbor_message,
+            get_cadence_stats,
+            mark_chat_read,
+            get_mentions,
+            get_plain_text,
+            verify_media,
+            export_anonymized,
+            get_media_ratio_timeline,
+            find_next_match,
+            get_volume_timeline,
+            get_messages_page,
+            find_media_references,
+            get_message_raw_lines,
+            get_initiative_balance,
+            get_balance,
+            query_messages,
+            get_recent_chats,
+            get_reply_targets,
+            export_media_bundle,
+            media_stripped_heuristic,
+            set_gap_threshold,
+            get_gap_threshold,
+            get_length_histogram,
+            get_active_dates,
+            get_chat_revision,
+            get_longest_message,
+            get_membership_timeline,
+            reparse_chat,
+            get_active_hours_per_sender,
+            search_grouped,
+            get_streaks
         ])
         .run(tauri::generate_context!())
         .expect("Error while running application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `contents` to a new temporary file with the given extension, returning its path
+    fn temp_file(contents: &[u8], extension: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{0}.{1}", Uuid::new_v4(), extension));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// Gets a chat to load from `path`, with any of the settings overridden by `settings`
+    fn chat_to_load(path: &Path, settings: serde_json::Value) -> ChatToLoad {
+        let mut chat = serde_json::json!({
+            "id": Uuid::new_v4(),
+            "file": path.to_str().unwrap(),
+            "directory": null,
+            "name": "Chat",
+            "starred": [],
+        });
+        for (key, value) in settings.as_object().unwrap() {
+            chat[key] = value.clone();
+        }
+        serde_json::from_value(chat).unwrap()
+    }
+
+    /// Parses the chat file at `path` with any of the settings overridden by `settings`
+    fn parse_file(path: &Path, settings: serde_json::Value) -> Result<ParsedWhatsAppChat, String> {
+        parse_whatsapp_export(
+            &chat_to_load(path, settings),
+            |_, _| {},
+            &AtomicBool::new(false),
+        )
+    }
+
+    /// Parses `text` as a chat file with the default settings
+    fn parse(text: &str) -> Result<ParsedWhatsAppChat, String> {
+        parse_file(&temp_file(text.as_bytes(), "txt"), serde_json::json!({}))
+    }
+
+    /// Compresses `text` with gzip
+    fn gzip(text: &str) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// A short chat in the NEW format, with a multi-line message and media
+    const FIXTURE: &str = "1/2/23, 1:00 PM - Messages and calls are end-to-end encrypted.\n1/2/23, 1:00 PM - Alice: hello\nthere\n1/2/23, 1:01 PM - Bob: IMG-20230102-WA0001.jpg (file attached)\nnice\n1/3/23, 9:15 AM - Alice: <Media omitted>\n1/3/23, 9:16 AM - Bob: ok\n";
+
+    #[test]
+    fn standalone_edited_notice_marks_any_message_edited() {
+        let chat = parse(
+            "[1/2/23, 1:00:00 PM] Alice: <attached: 00000001-PHOTO-2023-01-02.jpg>\n\u{200e}<This message was edited>\n[1/2/23, 1:01:00 PM] Bob: hi\n<This message was edited>\n",
+        )
+        .unwrap()
+        .chat;
+        assert_eq!(chat.messages.len(), 2);
+        assert!(chat.messages[0].edited);
+        assert!(
+            matches!(&chat.messages[0].content, MessageContent::Media(m) if m.caption.is_none())
+        );
+        assert!(chat.messages[1].edited);
+        assert_eq!(
+            chat.messages[1].content,
+            MessageContent::Text("hi".to_owned())
+        );
+    }
+
+    #[test]
+    fn blank_line_inside_multiline_message_is_kept() {
+        let chat = parse(
+            "1/2/23, 1:00 PM - Alice: first line\n\nthird line\n1/2/23, 1:01 PM - Bob: reply\n",
+        )
+        .unwrap()
+        .chat;
+        assert_eq!(chat.messages.len(), 2);
+        assert_eq!(
+            chat.messages[0].content,
+            MessageContent::Text("first line\n\nthird line".to_owned())
+        );
+        assert_eq!(chat.messages[0].source_lines, 1..4);
+        assert_eq!(
+            chat.messages[1].content,
+            MessageContent::Text("reply".to_owned())
+        );
+    }
+
+    #[test]
+    fn failed_save_leaves_old_save_intact() {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        create_dir_all(&directory).unwrap();
+        let saved = |recent| SavedChats {
+            version: SAVE_VERSION,
+            theme: Theme::default(),
+            chats: Vec::new(),
+            recent,
+            warnings: Vec::new(),
+        };
+        write_saved_chats(&directory, &saved(vec![Uuid::new_v4()])).unwrap();
+        let old = fs::read(directory.join(SAVE_NAME)).unwrap();
+        // A directory where the temporary file should go makes the write fail
+        create_dir_all(directory.join(format!("{0}.tmp", SAVE_NAME))).unwrap();
+        assert!(write_saved_chats(&directory, &saved(vec![Uuid::new_v4()])).is_err());
+        assert_eq!(fs::read(directory.join(SAVE_NAME)).unwrap(), old);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn attachment_with_trailing_caption() {
+        let chat = parse(
+            "[1/2/23, 1:00:00 PM] Alice: <attached: 00000001-PHOTO-2023-01-02.jpg> look at this\n",
+        )
+        .unwrap()
+        .chat;
+        assert_eq!(chat.messages.len(), 1);
+        let MessageContent::Media(media) = &chat.messages[0].content else {
+            panic!("Expected a media message");
+        };
+        assert_eq!(
+            media.file_name.as_deref(),
+            Some("00000001-PHOTO-2023-01-02.jpg")
+        );
+        assert_eq!(media.caption.as_deref(), Some("look at this"));
+        assert!(matches!(media.media_type, MediaType::PHOTO));
+    }
+
+    #[test]
+    fn star_round_trip() {
+        let chat = parse(
+            "1/2/23, 1:00 PM - Alice: one\n1/2/23, 1:01 PM - Bob: two\n1/2/23, 1:02 PM - Alice: three\n",
+        )
+        .unwrap()
+        .chat;
+        chat.toggle_star(1).unwrap();
+        let starred = chat.starred_messages();
+        assert_eq!(starred.len(), 1);
+        assert_eq!(starred[0].content, MessageContent::Text("two".to_owned()));
+        chat.toggle_star(starred[0].idx).unwrap();
+        assert!(chat.starred_messages().is_empty());
+        assert!(chat.toggle_star(3).is_err());
+    }
+
+    #[test]
+    fn gzip_parses_like_plain_text() {
+        let plain = parse(FIXTURE).unwrap().chat;
+        let gzipped = parse_file(&temp_file(&gzip(FIXTURE), "txt.gz"), serde_json::json!({}))
+            .unwrap()
+            .chat;
+        assert_eq!(plain.messages.len(), 5);
+        assert_eq!(
+            serde_json::to_value(&plain.messages).unwrap(),
+            serde_json::to_value(&gzipped.messages).unwrap()
+        );
+    }
+
+    #[test]
+    fn corrupt_gzip_is_an_error() {
+        let mut data = gzip(FIXTURE);
+        data.truncate(data.len() / 2);
+        assert!(parse_file(&temp_file(&data, "txt.gz"), serde_json::json!({})).is_err());
+    }
+
+    #[test]
+    fn truncated_last_line_is_skipped_with_warning() {
+        let parsed = parse(
+            "[1/2/23, 1:00:00 PM] Alice: hello\n[1/2/23, 1:01:00 PM] Bob: hi\nthere\n[1/2/23, 1:0",
+        )
+        .unwrap();
+        assert_eq!(parsed.chat.messages.len(), 2);
+        assert_eq!(
+            parsed.chat.messages[1].content,
+            MessageContent::Text("hi\nthere".to_owned())
+        );
+        assert_eq!(parsed.warnings.len(), 1);
+        assert!(parsed.warnings[0].contains("[1/2/23, 1:0"));
+    }
+
+    #[test]
+    fn system_message_actor_distinguishes_actor_from_subject() {
+        let senders = HashSet::from(["Alice".to_owned(), "Al".to_owned(), "Bob".to_owned()]);
+        assert_eq!(
+            system_message_actor("Alice added Bob", &senders).as_deref(),
+            Some("Alice")
+        );
+        assert_eq!(system_message_actor("Alice was added", &senders), None);
+        assert_eq!(
+            system_message_actor("Alice's security code changed", &senders),
+            None
+        );
+        assert_eq!(system_message_actor("Someone added Bob", &senders), None);
+    }
+
+    #[test]
+    fn system_messages_are_attributed_to_their_actor() {
+        let chat = parse(
+            "1/2/23, 1:00 PM - Alice: hi\n1/2/23, 1:01 PM - Alice added Bob\n1/2/23, 1:02 PM - Alice was added\n",
+        )
+        .unwrap()
+        .chat;
+        assert_eq!(chat.messages.len(), 3);
+        assert_eq!(chat.messages[1].sender.as_deref(), Some("Alice"));
+        assert_eq!(chat.messages[2].sender, None);
+    }
+
+    #[test]
+    fn media_sent_in_the_same_minute_keep_their_order_and_captions() {
+        let chat = parse(
+            "1/2/23, 1:05 PM - Alice: first\n1/2/23, 1:00 PM - Bob: IMG-20230102-WA0001.jpg (file attached)\nfirst caption\n1/2/23, 1:00 PM - Bob: IMG-20230102-WA0002.jpg (file attached)\nsecond caption\n1/2/23, 1:00 PM - Bob: done\n",
+        )
+        .unwrap()
+        .chat;
+        let contents: Vec<_> = chat
+            .messages
+            .iter()
+            .map(|m| match &m.content {
+                MessageContent::Media(media) => (media.file_name.clone(), media.caption.clone()),
+                MessageContent::Text(text) => (None, Some(text.clone())),
+                _ => panic!("Unexpected message"),
+            })
+            .collect();
+        assert_eq!(
+            contents,
+            [
+                (
+                    Some("IMG-20230102-WA0001.jpg".to_owned()),
+                    Some("first caption".to_owned())
+                ),
+                (
+                    Some("IMG-20230102-WA0002.jpg".to_owned()),
+                    Some("second caption".to_owned())
+                ),
+                (None, Some("done".to_owned())),
+                (None, Some("first".to_owned())),
+            ]
+        );
+        assert!(chat
+            .messages
+            .iter()
+            .enumerate()
+            .all(|(idx, m)| m.idx == idx));
+    }
+
+    #[test]
+    fn renamed_media_is_resolved() {
+        let directory = |names: &[&str]| {
+            DirectoryFiles::new(
+                names
+                    .iter()
+                    .map(|n| (n.to_string(), PathBuf::from("media").join(n)))
+                    .collect(),
+            )
+        };
+        let files = directory(&[
+            "IMG-20220101-WA0001.jpg",
+            "Chat - IMG-20220101-WA0002.jpg",
+            "IMG-20220101-WA0003 (1).jpg",
+            "A - IMG-20220101-WA0004.jpg",
+            "B - IMG-20220101-WA0004.jpg",
+        ]);
+        let mut ambiguous = Vec::new();
+        let mut resolve = |name| full_file_path(name, &files, &mut ambiguous);
+        assert!(resolve("IMG-20220101-WA0001.jpg")
+            .unwrap()
+            .ends_with("IMG-20220101-WA0001.jpg"));
+        assert!(resolve("IMG-20220101-WA0002.jpg")
+            .unwrap()
+            .ends_with("Chat - IMG-20220101-WA0002.jpg"));
+        assert!(resolve("IMG-20220101-WA0003.jpg")
+            .unwrap()
+            .ends_with("IMG-20220101-WA0003 (1).jpg"));
+        assert_eq!(resolve("IMG-20220101-WA0004.jpg"), None);
+        assert_eq!(resolve(""), None);
+        assert_eq!(resolve("IMG-20220101-WA0005.jpg"), None);
+        assert_eq!(ambiguous, ["IMG-20220101-WA0004.jpg"]);
+    }
+
+    #[test]
+    fn day_first_timestamps_before_the_13th_are_read_day_first() {
+        let parsed = parse(
+            "1/2/23, 1:00 PM - Alice: one\n5/2/23, 1:00 PM - Bob: two\n13/2/23, 1:00 PM - Alice: three\n",
+        )
+        .unwrap();
+        let dates: Vec<_> = parsed
+            .chat
+            .messages
+            .iter()
+            .map(|m| m.timestamp.date())
+            .collect();
+        assert_eq!(
+            dates,
+            [
+                NaiveDate::from_ymd_opt(2023, 2, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2023, 2, 5).unwrap(),
+                NaiveDate::from_ymd_opt(2023, 2, 13).unwrap(),
+            ]
+        );
+        assert_eq!(parsed.chat.timestamp_format, "%d/%m/%y, %I:%M %p");
+        assert_eq!(parsed.warnings.len(), 1);
+        assert!(!parsed.warnings[0].contains("may have the wrong timestamp"));
+    }
+
+    #[test]
+    fn progress_counts_bytes_of_the_file() {
+        // Windows-1252 text takes more bytes once transcoded to UTF-8
+        let text = "1/2/23, 1:00 PM - Alice: caf\u{e9} \u{e9}\u{e9}\u{e9}\n".repeat(50);
+        let data = WINDOWS_1252.encode(&text).0.into_owned();
+        let path = temp_file(&data, "txt");
+        let mut progress = Vec::new();
+        parse_whatsapp_export(
+            &chat_to_load(&path, serde_json::json!({ "encoding": "windows-1252" })),
+            |bytes_read, total_bytes| progress.push((bytes_read, total_bytes)),
+            &AtomicBool::new(false),
+        )
+        .unwrap();
+        assert!(progress
+            .iter()
+            .all(|&(read, total)| read <= total && total == data.len() as u64));
+        assert_eq!(
+            progress.last(),
+            Some(&(data.len() as u64, data.len() as u64))
+        );
+    }
+
+    #[test]
+    fn recent_chat_is_only_written_when_it_moves() {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        create_dir_all(&directory).unwrap();
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        write_saved_chats(
+            &directory,
+            &SavedChats {
+                version: SAVE_VERSION,
+                theme: Theme::default(),
+                chats: Vec::new(),
+                recent: vec![first, second],
+                warnings: Vec::new(),
+            },
+        )
+        .unwrap();
+        // A directory where the temporary file should go makes any write fail
+        create_dir_all(directory.join(format!("{0}.tmp", SAVE_NAME))).unwrap();
+        assert!(record_recent_chat(&directory, first).is_ok());
+        assert!(record_recent_chat(&directory, second).is_err());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn large_file_that_isnt_a_chat_is_rejected() {
+        let path = temp_file(&vec![b'x'; 10 * MAX_FIRST_LINE as usize], "txt");
+        let Err(err) = parse_file(&path, serde_json::json!({ "max_file_size": 1024 })) else {
+            panic!("Expected the file to be rejected");
+        };
+        assert!(err.contains("doesn't look like a WhatsApp chat export"));
+        let path = temp_file(FIXTURE.repeat(20).as_bytes(), "txt");
+        assert!(parse_file(&path, serde_json::json!({ "max_file_size": 1024 })).is_ok());
+    }
+
+    #[test]
+    fn memoized_sender_counts_match_a_full_count() {
+        let chat = parse(FIXTURE).unwrap().chat;
+        let full_count = |c: &WhatsAppChat| {
+            let you = c.you.lock().unwrap().clone();
+            let mut counts = HashMap::new();
+            WhatsAppChat::add_counts(&mut counts, &c.messages, &you);
+            serde_json::to_value(counts).unwrap()
+        };
+        let cached_count = |c: &WhatsAppChat| serde_json::to_value(c.count_by_sender()).unwrap();
+        assert_eq!(cached_count(&chat), full_count(&chat));
+        *chat.you.lock().unwrap() = vec!["Alice".to_owned()];
+        assert_eq!(cached_count(&chat), full_count(&chat));
+        let mut changed = chat.clone();
+        changed.messages[1].automated = true;
+        changed.messages.pop();
+        changed.bump_revision();
+        assert_eq!(cached_count(&changed), full_count(&changed));
+        assert_ne!(cached_count(&changed), cached_count(&chat));
+        assert_eq!(cached_count(&chat), full_count(&chat));
+        // Starring a message changes the chat's revision, but not the revision the counts depend on
+        let (revision, combined) = (chat.revision.load(Relaxed), chat.combined_revision());
+        chat.toggle_star(0).unwrap();
+        assert_eq!(chat.revision.load(Relaxed), revision);
+        assert!(chat.combined_revision() > combined);
+        assert_eq!(cached_count(&chat), full_count(&chat));
+    }
+
+    #[test]
+    fn iso_dates_are_parsed() {
+        let parsed =
+            parse("2023-01-02, 13:05 - Alice: hello\nmore - text\n2023-01-13, 09:06 - Bob: hi\n")
+                .unwrap();
+        let messages = &parsed.chat.messages;
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[0].content,
+            MessageContent::Text("hello\nmore - text".to_owned())
+        );
+        assert_eq!(
+            messages[1].timestamp,
+            NaiveDate::from_ymd_opt(2023, 1, 13)
+                .unwrap()
+                .and_hms_opt(9, 6, 0)
+                .unwrap()
+        );
+        assert_eq!(parsed.chat.timestamp_format, "%Y-%m-%d, %H:%M");
+        let chat = parse("2023-01-02, 1:05 PM - Alice: hello\n").unwrap().chat;
+        assert_eq!(chat.timestamp_format, "%Y-%m-%d, %I:%M %p");
+        assert_eq!(chat.messages[0].timestamp.hour(), 13);
+    }
+
+    #[test]
+    fn busiest_days_start_at_their_first_message() {
+        let chat = parse(FIXTURE).unwrap().chat;
+        let days = chat.busiest_days(10);
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].date, NaiveDate::from_ymd_opt(2023, 1, 2).unwrap());
+        assert_eq!((days[0].count, days[0].first_idx), (3, 0));
+        assert_eq!(days[0].media.photo, 1);
+        assert_eq!((days[1].count, days[1].first_idx), (2, 3));
+        assert_eq!(days[1].by_sender.get("Alice"), Some(&1));
+        assert_eq!(chat.busiest_days(1).len(), 1);
+    }
+
+    #[test]
+    fn revision_increases_when_a_chat_is_parsed_again() {
+        let chat = parse(FIXTURE).unwrap().chat;
+        let first = chat.revision.load(Relaxed);
+        chat.bump_revision();
+        let bumped = chat.revision.load(Relaxed);
+        assert!(bumped > first);
+        let reparsed = parse(FIXTURE).unwrap().chat;
+        assert!(reparsed.revision.load(Relaxed) > bumped);
+    }
+
+    #[test]
+    fn membership_changes_are_anchored_on_known_senders() {
+        let senders = HashSet::from(["Alice".to_owned(), "Mary".to_owned(), "Mary Ann".to_owned()]);
+        let change = |text| membership_change(text, &senders);
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            change("Alice added Bob, Carol and you"),
+            Some((SystemEvent::ADDED, names(&["Bob", "Carol", "You"])))
+        );
+        assert_eq!(
+            change("Mary Ann removed Bob"),
+            Some((SystemEvent::REMOVED, names(&["Bob"])))
+        );
+        assert_eq!(
+            change("You added Dave"),
+            Some((SystemEvent::ADDED, names(&["Dave"])))
+        );
+        assert_eq!(
+            change("Alice created group \"Trip\""),
+            Some((SystemEvent::JOINED, names(&["Alice"])))
+        );
+        assert_eq!(
+            change("Bob left"),
+            Some((SystemEvent::LEFT, names(&["Bob"])))
+        );
+        assert_eq!(
+            change("Dave joined using this group's invite link"),
+            Some((SystemEvent::JOINED, names(&["Dave"])))
+        );
+        assert_eq!(
+            change("You were added"),
+            Some((SystemEvent::ADDED, names(&["You"])))
+        );
+        // Not by a known sender
+        assert_eq!(change("The admin added Bob"), None);
+        assert_eq!(change("Bob's note: Carol removed Dave"), None);
+        assert_eq!(change("Alice changed the subject to \"we left\""), None);
+    }
+
+    #[test]
+    fn membership_events_are_recognized_when_parsing() {
+        let chat = parse(
+            "1/2/23, 1:00 PM - Alice: hi\n1/2/23, 1:01 PM - Alice added Bob\n1/2/23, 1:02 PM - Bob left\n",
+        )
+        .unwrap()
+        .chat;
+        assert_eq!(chat.messages[1].system_event, Some(SystemEvent::ADDED));
+        assert_eq!(chat.messages[2].system_event, Some(SystemEvent::LEFT));
+    }
+
+    #[test]
+    fn chat_cache_round_trip() {
+        let path = temp_file(FIXTURE.as_bytes(), "txt");
+        let chat = chat_to_load(&path, serde_json::json!({ "starred": [1] }));
+        let parsed = parse_whatsapp_export(&chat, |_, _| {}, &AtomicBool::new(false)).unwrap();
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        write_chat_cache(&directory, &chat, &parsed).unwrap();
+        let cached = read_chat_cache(&directory, &chat).unwrap();
+        assert_eq!(
+            serde_json::to_value(&cached.chat.messages).unwrap(),
+            serde_json::to_value(&parsed.chat.messages).unwrap()
+        );
+        assert!(cached
+            .chat
+            .messages
+            .iter()
+            .zip(parsed.chat.messages.iter())
+            .all(|(a, b)| a.source_lines == b.source_lines));
+        assert!(cached.chat.messages[1].starred.load(Relaxed));
+        assert_eq!(cached.chat.timestamp_format, parsed.chat.timestamp_format);
+        assert_eq!(cached.warnings, parsed.warnings);
+        // Stars are taken from the chat being loaded rather than the cache
+        let unstarred = ChatToLoad {
+            starred: Vec::new(),
+            ..chat_to_load(&path, serde_json::json!({ "id": chat.id }))
+        };
+        assert!(!read_chat_cache(&directory, &unstarred)
+            .unwrap()
+            .chat
+            .messages[1]
+            .starred
+            .load(Relaxed));
+        remove_chat_cache(&directory, chat.id);
+        assert!(read_chat_cache(&directory, &chat).is_none());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn chat_cache_is_invalidated_by_changes() {
+        let media = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        create_dir_all(&media).unwrap();
+        let path = temp_file(FIXTURE.as_bytes(), "txt");
+        let settings = serde_json::json!({ "directory": media.to_str().unwrap() });
+        let chat = chat_to_load(&path, settings.clone());
+        let parsed = parse_whatsapp_export(&chat, |_, _| {}, &AtomicBool::new(false)).unwrap();
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        write_chat_cache(&directory, &chat, &parsed).unwrap();
+        assert!(read_chat_cache(&directory, &chat).is_some());
+        // Different settings
+        let mut changed = chat_to_load(&path, settings);
+        changed.id = chat.id;
+        changed.you = vec!["Alice".to_owned()];
+        assert!(read_chat_cache(&directory, &changed).is_none());
+        // Media added to the media directory
+        fs::write(media.join("IMG-20230102-WA0001.jpg"), b"").unwrap();
+        File::open(&media)
+            .unwrap()
+            .set_modified(SystemTime::now() + std::time::Duration::from_secs(60))
+            .unwrap();
+        assert!(read_chat_cache(&directory, &chat).is_none());
+        write_chat_cache(&directory, &chat, &parsed).unwrap();
+        assert!(read_chat_cache(&directory, &chat).is_some());
+        // A changed chat file
+        fs::write(
+            &path,
+            format!("{0}1/3/23, 9:17 AM - Alice: more\n", FIXTURE),
+        )
+        .unwrap();
+        assert!(read_chat_cache(&directory, &chat).is_none());
+        fs::remove_dir_all(&directory).unwrap();
+        fs::remove_dir_all(&media).unwrap();
+    }
+
+    #[test]
+    fn removing_a_chat_keeps_the_others_and_their_caches() {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let path = temp_file(FIXTURE.as_bytes(), "txt");
+        let mut chats = Vec::new();
+        let mut to_load = Vec::new();
+        for name in ["First", "Second", "Third"] {
+            let chat = chat_to_load(&path, serde_json::json!({ "name": name }));
+            let parsed = parse_whatsapp_export(&chat, |_, _| {}, &AtomicBool::new(false)).unwrap();
+            write_chat_cache(&directory, &chat, &parsed).unwrap();
+            chats.push(Arc::new(parsed.chat));
+            to_load.push(chat);
+        }
+        remove_loaded_chat(&mut chats, "Second", Some(&directory));
+        let names: Vec<&str> = chats.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["First", "Third"]);
+        assert!(read_chat_cache(&directory, &to_load[0]).is_some());
+        assert!(read_chat_cache(&directory, &to_load[1]).is_none());
+        assert!(read_chat_cache(&directory, &to_load[2]).is_some());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
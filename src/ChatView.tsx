@@ -1,7 +1,7 @@
 import { RefObject, useEffect, useRef, useState } from "react";
 import { ListBox } from "primereact/listbox";
-import { chat_settings_t, chat_summary_t, global_settings_t, media_content_t, media_t, returned_chat_t, statistics_t, system_content_t, text_content_t } from "./types";
-import { getMessageType } from "./utilities";
+import { chat_settings_t, chat_summary_t, global_settings_t, media_content_t, media_t, returned_chat_t, statistics_t, text_content_t } from "./types";
+import { getMessageType, getSystemText } from "./utilities";
 import Chat from "./Chat";
 import { BulkMediaMessage, MediaMessage, Message, SystemMessage, TextMessage } from "./messages";
 import { invoke } from "@tauri-apps/api/core";
@@ -41,7 +41,7 @@ interface ChatViewProps {
 
 export default function ChatView(props: ChatViewProps) {
     const default_settings: Record<string, chat_settings_t> = {};
-    props.summaries.forEach(s => { default_settings[s.name] = { you: null } });
+    props.summaries.forEach(s => { default_settings[s.name] = { you: [] } });
     const [currentSettings, setCurrentSettings] = useState<typeof default_settings>(default_settings);
     const [selectedChat, setSelectedChat] = useState<chat_summary_t | null>(null);
     const [loadedMessages, setLoadedMessages] = useState<Message[] | null>(null);
@@ -66,23 +66,23 @@ export default function ChatView(props: ChatViewProps) {
      */
     const changeSelectedChat = (s: chat_summary_t | null) => {
         if (s != null) {
-            invoke("get_chat", { chat: s.name })
+            invoke("get_messages_page", { chat: s.name, offset: 0 })
                 .then(res => {
-                    const resp = res as { directories: string[], name: string, messages: returned_chat_t[] };
+                    const messages = res as returned_chat_t[];
                     const loaded_messages: typeof loadedMessages = [];
                     let i = 0;
-                    while (i < resp.messages.length) {
-                        const r = resp.messages[i];
+                    while (i < messages.length) {
+                        const r = messages[i];
                         if (getMessageType(r.content) === "text") {
                             loaded_messages.push(new TextMessage(r.timestamp, r.sender, (r.content as text_content_t).Text, loaded_messages.length, r.idx, r.starred));
                         }
                         else if (getMessageType(r.content) === "media") {
                             const c = (r.content as media_content_t).Media;
-                            if (c.caption == null && i < resp.messages.length - 1) {
+                            if (c.caption == null && i < messages.length - 1) {
                                 let next_non_media_idx = i + 1;
                                 const r_date = new Date(r.timestamp).getTime();
-                                while (next_non_media_idx < resp.messages.length) {
-                                    const next_message = resp.messages[next_non_media_idx];
+                                while (next_non_media_idx < messages.length) {
+                                    const next_message = messages[next_non_media_idx];
                                     const next_date = new Date(next_message.timestamp).getTime();
                                     if (getMessageType(next_message.content) === "media" &&
                                         next_message.sender === r.sender &&
@@ -103,10 +103,10 @@ export default function ChatView(props: ChatViewProps) {
                                     const backend_idxes = [];
                                     const timestamps = [];
                                     for (let j = i; j < next_non_media_idx; j++) {
-                                        media_types.push((resp.messages[j].content as media_content_t).Media.media_type);
-                                        paths.push((resp.messages[j].content as media_content_t).Media.path!);
-                                        backend_idxes.push(resp.messages[j].idx);
-                                        timestamps.push(resp.messages[j].timestamp);
+                                        media_types.push((messages[j].content as media_content_t).Media.media_type);
+                                        paths.push((messages[j].content as media_content_t).Media.path!);
+                                        backend_idxes.push(messages[j].idx);
+                                        timestamps.push(messages[j].timestamp);
                                     }
                                     loaded_messages.push(new BulkMediaMessage(r.timestamp, r.sender, media_types, paths, backend_idxes, timestamps, loaded_messages.length, r.idx, r.starred));
                                     i = next_non_media_idx;
@@ -121,7 +121,7 @@ export default function ChatView(props: ChatViewProps) {
                             }
                         }
                         else {
-                            loaded_messages.push(new SystemMessage(r.timestamp, r.sender, (r.content as system_content_t).System, loaded_messages.length, r.idx, r.starred));
+                            loaded_messages.push(new SystemMessage(r.timestamp, r.sender, getSystemText(r.content), loaded_messages.length, r.idx, r.starred));
                         }
                         i++;
                     }
@@ -139,7 +139,7 @@ export default function ChatView(props: ChatViewProps) {
      */
     const starMessage = (message: Message) => {
         if (selectedChat != null && loadedMessages != null) {
-            invoke("star_message", { chat: selectedChat?.name, messageIdx: message.backend_idx })
+            invoke("star_message", { chat: selectedChat?.name, id: selectedChat?.id, messageIdx: message.backend_idx })
                 .then(() => {
                     const new_chat = [...loadedMessages];
                     new_chat[message.idx].starred = !message.starred;
@@ -176,7 +176,7 @@ export default function ChatView(props: ChatViewProps) {
         const message_type = getMessageType(summary.last_message?.content);
         const message = message_type == null ? null
             : message_type === "text" ? (summary.last_message!.content as text_content_t).Text
-                : message_type === "system" ? (summary.last_message!.content as system_content_t).System
+                : message_type === "system" || message_type === "deleted" ? getSystemText(summary.last_message!.content)
                     : `sent a ${getMediaText(summary.last_message!.content as media_content_t)}`;
         return <div>
             <b style={{ display: "inline-block", width: "5.25vw", whiteSpace: "nowrap", overflow: "hidden", textOverflow: "ellipsis" }}>{summary.name}</b>
@@ -196,7 +196,7 @@ export default function ChatView(props: ChatViewProps) {
      */
     const loadStarred = () => {
         if (selectedChat != null) {
-            invoke("get_starred", { chat: selectedChat.name })
+            invoke("get_starred", { chat: selectedChat.name, id: selectedChat.id })
                 .then(res => {
                     const resp = res as returned_chat_t[];
                     const starred_messages: typeof starredMessages = [];
@@ -219,7 +219,7 @@ export default function ChatView(props: ChatViewProps) {
                             starred_messages.push(new TextMessage(m.timestamp, m.sender, (m.content as text_content_t).Text, idx, m.idx, m.starred));
                         }
                         else {
-                            starred_messages.push(new SystemMessage(m.timestamp, m.sender, (m.content as system_content_t).System, idx, m.idx, m.starred));
+                            starred_messages.push(new SystemMessage(m.timestamp, m.sender, getSystemText(m.content), idx, m.idx, m.starred));
                         }
                     }
                     setStarredMessages(starred_messages);
@@ -309,7 +309,7 @@ export default function ChatView(props: ChatViewProps) {
                                             rowIndex={index}
                                         >
                                             {({ measure }) => {
-                                                const fromYou = loadedMessages[index].sender != null && loadedMessages[index].sender === currentSettings[selectedChat.name].you;
+                                                const fromYou = loadedMessages[index].sender != null && currentSettings[selectedChat.name].you.includes(loadedMessages[index].sender!);
                                                 return <div style={{ ...style, paddingTop: "5px", paddingBottom: "5px" }}>
                                                     <Chat message={loadedMessages[index]} onContentChange={measure} starMessage={starMessage} systemMessageWidth={"80vw"} fromYou={fromYou} />
                                                 </div>
@@ -63,8 +63,8 @@ export function LoadChats(props: LoadChatsProps) {
             multiple: false,
             filters: [
                 {
-                    name: "Text file (*.txt)",
-                    extensions: ["txt"],
+                    name: "Text file (*.txt, *.txt.gz)",
+                    extensions: ["txt", "gz"],
                 }
             ]
         });
@@ -112,7 +112,7 @@ export function LoadChats(props: LoadChatsProps) {
                     name: selectedChatName.trim(),
                     editable: true,
                     starred: [],
-                    you: null,
+                    you: [],
                 });
                 setSelectedFiles(new_chats);
                 cancelChooseChat();
@@ -140,7 +140,7 @@ export function LoadChats(props: LoadChatsProps) {
                             name: selectedChatName.trim(),
                             editable: true,
                             starred: [],
-                            you: null,
+                            you: [],
                         });
                     }
                     else {
@@ -181,7 +181,7 @@ export function LoadChats(props: LoadChatsProps) {
      */
     const doLoad = () => {
         setLoading(true);
-        invoke("load_chats", { chats: selectedFiles })
+        invoke("load_chats", { chats: selectedFiles, useCache: true })
             .then(res => {
                 const resp = res as returned_chat_summary_t[];
                 props.setChatSummaries(resp.map(summary => {
@@ -196,7 +196,14 @@ export function LoadChats(props: LoadChatsProps) {
                     };
                 }))
             })
-            .catch(err => props.toast.current?.show({ severity: "error", summary: "Error loading chats", detail: err }))
+            .catch(err => {
+                if (err === "Cancelled") {
+                    props.toast.current?.show({ severity: "info", summary: "Loading cancelled" });
+                }
+                else {
+                    props.toast.current?.show({ severity: "error", summary: "Error loading chats", detail: err });
+                }
+            })
             .finally(() => setLoading(false));
     }
 
@@ -253,7 +260,9 @@ export function LoadChats(props: LoadChatsProps) {
     useEffect(() => {
         invoke("get_saved_chats")
             .then((res) => {
-                setSelectedFiles((res as { chats: chat_files_t[] }).chats);
+                const resp = res as { chats: chat_files_t[], warnings?: string[] };
+                setSelectedFiles(resp.chats);
+                resp.warnings?.forEach(w => props.toast.current?.show({ severity: "warn", summary: "Saved chats", detail: w }));
             });
         invoke("get_set_theme_initial", { "theme": window.matchMedia("(prefers-color-scheme: dark)").matches ? "DARK" : "LIGHT" })
             .then((res) => {
@@ -299,6 +308,7 @@ export function LoadChats(props: LoadChatsProps) {
                     </DataTable>
                     <div className="settings-form2">
                         <Button label="Load" icon="pi pi-arrow-right" severity="success" disabled={selectedFiles.length === 0} loading={loading} onClick={load} className="right-pad" />
+                        {loading ? <Button label="Cancel" icon="pi pi-times" severity="secondary" outlined onClick={() => invoke("cancel_load")} className="right-pad" /> : null}
                         <Button label="Settings" icon="pi pi-cog" onClick={() => setShowSettings(true)} />
                     </div>
                 </div>
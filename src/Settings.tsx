@@ -1,6 +1,6 @@
 import { Button } from "primereact/button";
 import { Dialog } from "primereact/dialog";
-import { Dropdown } from "primereact/dropdown";
+import { MultiSelect } from "primereact/multiselect";
 import { FormEvent, useState } from "react";
 import { chat_settings_t, global_settings_t } from "./types";
 import { InputSwitch } from "primereact/inputswitch";
@@ -39,7 +39,7 @@ interface ChatSettingsProps {
  * Dialog for settings
  */
 export function ChatSettings(props: ChatSettingsProps) {
-    const [you, setYou] = useState<string | null>(props.currentChatSettings.you);
+    const [you, setYou] = useState<string[]>(props.currentChatSettings.you);
     const [lightMode, setLightMode] = useState(props.currentGlobalSettings.lightMode);
 
     /**
@@ -48,7 +48,7 @@ export function ChatSettings(props: ChatSettingsProps) {
      */
     const reset = (e: FormEvent) => {
         e.preventDefault();
-        setYou(null);
+        setYou([]);
         setLightMode(!window.matchMedia("(prefers-color-scheme: dark)").matches);
     }
 
@@ -76,7 +76,7 @@ export function ChatSettings(props: ChatSettingsProps) {
             <h2 className="text-center">Chat settings</h2>
             <div style={{ textAlign: "center", marginTop: "5px" }}>
                 <label htmlFor="you" className="right-pad">You:</label>
-                <Dropdown value={you} onChange={e => setYou(e.value)} options={Array.from(props.senders)} inputId="you" />
+                <MultiSelect value={you} onChange={e => setYou(e.value)} options={Array.from(props.senders)} inputId="you" />
             </div>
             <h2 className="text-center">Global settings</h2>
             <div className="flex-align-center settings-form">
@@ -14,7 +14,7 @@ type statistic_row_t = {
      */
     text: number,
     /**
-     * Total number of media messages (sum of `photo`, `video`, `audio` and `other`) sent
+     * Total number of media messages (sum of `photo`, `video`, `audio`, `voice_note` and `other`) sent
      */
     media: number,
     /**
@@ -26,13 +26,25 @@ type statistic_row_t = {
      */
     video: number,
     /**
-     * Number of audio recordings sent
+     * Number of audio files sent
      */
     audio: number,
+    /**
+     * Number of voice notes sent
+     */
+    voice_note: number,
     /**
      * Number of other files sent
      */
     other: number,
+    /**
+     * Number of sent messages that were later deleted
+     */
+    deleted: number,
+    /**
+     * Number of automated messages sent
+     */
+    automated: number,
     /**
      * Total number of messages sent
      */
@@ -77,7 +89,10 @@ export default function Statistics(props: StatisticsProps) {
         photo: 0,
         video: 0,
         audio: 0,
+        voice_note: 0,
         other: 0,
+        deleted: 0,
+        automated: 0,
         total: 0
     };
     const vals: statistic_row_t[] = Object.entries(props.stats).map(([sender, v]) => {
@@ -86,8 +101,11 @@ export default function Statistics(props: StatisticsProps) {
         total.photo += v.media.photo;
         total.video += v.media.video;
         total.audio += v.media.audio;
+        total.voice_note += v.media.voice_note;
         total.other += v.media.other;
-        total.total += v.text + v.system + sum(Object.values(v.media));
+        total.deleted += v.deleted;
+        total.automated += v.automated;
+        total.total += v.text + v.system + v.deleted + v.automated + sum(Object.values(v.media));
         return {
             sender,
             text: v.text,
@@ -95,8 +113,11 @@ export default function Statistics(props: StatisticsProps) {
             photo: v.media.photo,
             video: v.media.video,
             audio: v.media.audio,
+            voice_note: v.media.voice_note,
             other: v.media.other,
-            total: v.text + v.system + sum(Object.values(v.media))
+            deleted: v.deleted,
+            automated: v.automated,
+            total: v.text + v.system + v.deleted + v.automated + sum(Object.values(v.media))
         }
     });
     vals.push(total);
@@ -108,8 +129,11 @@ export default function Statistics(props: StatisticsProps) {
             <Column header="Photos" field="photo" dataType="numeric" sortable body={row => mediaPartTemplate(row.photo, row.sender === "Total")} />
             <Column header="Videos" field="video" dataType="numeric" sortable body={row => mediaPartTemplate(row.video, row.sender === "Total")} />
             <Column header="Audio" field="audio" dataType="numeric" sortable body={row => mediaPartTemplate(row.audio, row.sender === "Total")} />
+            <Column header="Voice notes" field="voice_note" dataType="numeric" sortable body={row => mediaPartTemplate(row.voice_note, row.sender === "Total")} />
             <Column header="Unknown files" field="other" dataType="numeric" sortable body={row => mediaPartTemplate(row.other, row.sender === "Total")} />
             <Column header="Media" field="media" dataType="numeric" sortable body={row => row.sender === "Total" ? <b>{row.media}</b> : row.media} />
+            <Column header="Automated" field="automated" dataType="numeric" sortable body={row => row.sender === "Total" ? <b>{row.automated}</b> : row.automated} />
+            <Column header="Deleted" field="deleted" dataType="numeric" sortable body={row => row.sender === "Total" ? <b>{row.deleted}</b> : row.deleted} />
             <Column header="Total" field="total" dataType="numeric" sortable body={row => <b>{row.total}</b>} />
         </DataTable>
     </Dialog>
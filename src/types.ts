@@ -27,9 +27,45 @@ export type chat_files_t = {
      */
     starred: number[],
     /**
-     * Sender considered to be "you"
+     * Senders considered to be "you"
      */
-    you: string | null
+    you: string[],
+    /**
+     * Minutes to shift the exported timestamps by, if the export was made in a different timezone
+     */
+    tz_offset_minutes?: number | null,
+    /**
+     * Display color chosen for each sender
+     */
+    sender_colors?: Record<string, string>,
+    /**
+     * Whether senders whose names differ only in case should be treated as the same person
+     */
+    merge_sender_case?: boolean,
+    /**
+     * Patterns identifying automated messages (such as business greetings and away messages)
+     */
+    automated_patterns?: string[],
+    /**
+     * Export version to parse the chat as; it's detected automatically if not given
+     */
+    format_hint?: "OLD" | "NEW" | null,
+    /**
+     * Label of the encoding of the chat file (such as "windows-1252"); it's detected automatically if not given
+     */
+    encoding?: string | null,
+    /**
+     * Index of the last message that has been read, if any
+     */
+    last_read_idx?: number | null,
+    /**
+     * Gap (in minutes) between messages after which a new conversation starts, if it's been set for this chat
+     */
+    gap_threshold_minutes?: number | null,
+    /**
+     * Size (in bytes) above which the file is only loaded if it looks like a chat export
+     */
+    max_file_size?: number | null
 }
 
 /**
@@ -44,10 +80,18 @@ export type media_t = {
      * Media absolute path, if available
      */
     path: string | null,
+    /**
+     * Name of the attached file, if known
+     */
+    file_name: string | null,
     /**
      * Media caption, if any
      */
-    caption: string | null
+    caption: string | null,
+    /**
+     * Whether the media is a recorded voice note
+     */
+    voice_note: boolean
 }
 
 /**
@@ -62,11 +106,26 @@ export type media_content_t = { Media: media_t };
  * System content
  */
 export type system_content_t = { System: string };
+/**
+ * Deleted content
+ */
+export type deleted_content_t = {
+    Deleted: {
+        /**
+         * Whether the message was deleted by "you"
+         */
+        deleted_by_you: boolean,
+        /**
+         * Name of the group admin who deleted the message, if it was deleted by one
+         */
+        deleted_by_admin: string | null
+    }
+};
 
 /**
  * Represents the content of a message
  */
-export type message_content_t = text_content_t | system_content_t | media_content_t
+export type message_content_t = text_content_t | system_content_t | media_content_t | deleted_content_t
 
 /**
  * Represents a message
@@ -91,7 +150,19 @@ export type message_t = {
     /**
      * Whether the chat has been starred
      */
-    starred: boolean
+    starred: boolean,
+    /**
+     * Whether the message was edited after being sent
+     */
+    edited: boolean,
+    /**
+     * Whether the message matches one of the chat's automated message patterns
+     */
+    automated: boolean,
+    /**
+     * The event the message describes, if it's a recognized system message
+     */
+    system_event: "PINNED" | "UNPINNED" | "ADDED" | "REMOVED" | "JOINED" | "LEFT" | null
 }
 
 /**
@@ -117,6 +188,10 @@ export type chat_summary_t = {
      * Warnings when loading the chat
      */
     warnings: string[],
+    /**
+     * Unique chat ID
+     */
+    id: string,
     /**
      * Chat name
      */
@@ -137,14 +212,34 @@ export type chat_summary_t = {
      * The total number of messages in the chat
      */
     number_of_messages: number,
+    /**
+     * Number of media messages whose file was found
+     */
+    media_resolved: number,
+    /**
+     * Number of media messages with a file name whose file wasn't found
+     */
+    media_unresolved: number,
+    /**
+     * Number of media messages that were omitted from the export
+     */
+    media_omitted: number,
     /**
      * Starred messages
      */
     starred: message_t[],
     /**
-     * Which sender is considered "you"
+     * Which senders are considered "you"
+     */
+    you: string[],
+    /**
+     * Minutes the exported timestamps were shifted by, if any
      */
-    you: string | null,
+    tz_offset_minutes: number | null,
+    /**
+     * Index of the last message that has been read, if any
+     */
+    last_read_idx: number | null,
 }
 
 /**
@@ -167,9 +262,9 @@ export type global_settings_t = {
  */
 export type chat_settings_t = {
     /**
-     * Who "you" are (for right-aligned messages)
+     * Who "you" are (for right-aligned messages); a person may appear under several names
      */
-    you: string | null
+    you: string[]
 }
 
 /**
@@ -184,6 +279,18 @@ export type statistics_t = Record<string, {
      * Number of sent/associated system messages
      */
     system: number,
+    /**
+     * Number of sent media messages with a caption
+     */
+    captioned_media: number,
+    /**
+     * Number of sent messages that were later deleted
+     */
+    deleted: number,
+    /**
+     * Number of automated text messages (not included in `text`)
+     */
+    automated: number,
     /**
      * Number of sent audio messages
      */
@@ -197,9 +304,13 @@ export type statistics_t = Record<string, {
          */
         video: number,
         /**
-         * Number of sent recordings
+         * Number of sent audio files, excluding voice notes
          */
         audio: number,
+        /**
+         * Number of sent voice notes
+         */
+        voice_note: number,
         /**
          * Number of sent files/unknown types
          */
@@ -1,4 +1,4 @@
-import { message_content_t } from "./types";
+import { deleted_content_t, message_content_t, system_content_t } from "./types";
 
 /**
  * Gets the basename from a file `path`. From https://stackoverflow.com/a/15270931
@@ -12,7 +12,7 @@ export function getBasename(path: string) {
 /**
  * Gets the type of the given message
  * @param msg Message content for which to get the type
- * @returns `null` if `msg` is nullish, "text" for text messages, "media" for media messages, and "system" otherwise
+ * @returns `null` if `msg` is nullish, "text" for text messages, "media" for media messages, "deleted" for deleted messages, and "system" otherwise
  */
 export function getMessageType(msg: message_content_t | null | undefined) {
     if (msg == null) {
@@ -24,9 +24,26 @@ export function getMessageType(msg: message_content_t | null | undefined) {
     else if (msg.hasOwnProperty("Media")) {
         return "media";
     }
+    else if (msg.hasOwnProperty("Deleted")) {
+        return "deleted";
+    }
     return "system";
 }
 
+/**
+ * Gets the text to display for a system or deleted message
+ * @param msg Message content for which to get the text
+ * @returns The system message text, or a notice describing who deleted the message
+ */
+export function getSystemText(msg: message_content_t) {
+    if (msg.hasOwnProperty("Deleted")) {
+        const deletion = (msg as deleted_content_t).Deleted;
+        return deletion.deleted_by_admin != null ? `This message was deleted by admin ${deletion.deleted_by_admin}`
+            : deletion.deleted_by_you ? "You deleted this message" : "This message was deleted";
+    }
+    return (msg as system_content_t).System;
+}
+
 /**
  * Simple, **insecure** numeric hash derived from https://gist.github.com/jlevy/c246006675becc446360a798e2b2d781
  * @param str String to hash